@@ -0,0 +1,82 @@
+//! Renders rows of principled spheres with increasing roughness, dielectric on top and metal below.
+//! `cargo run --release --example roughness_sweep -- roughness.ppm`
+use std::{
+    env::args,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use raytracing_iow::{
+    color::Color,
+    materials::{lambertian::Lambertain, principled::Principled},
+    render::{
+        camera::{Camera, CameraConfig},
+        cancel::CancelToken,
+        filter::Filter,
+        screen::Screen,
+        viewport::ViewportConfig,
+        PixelLocator,
+    },
+    shapes::sphere::Sphere,
+    world::{Object, World},
+};
+
+const STEPS: u32 = 6;
+
+fn main() -> io::Result<()> {
+    let filename = args().nth(1).unwrap_or("roughness_sweep.ppm".to_owned());
+
+    let screen = Screen::new(900, 360);
+    let camera = Camera::new(
+        CameraConfig {
+            samples_per_pixel: 64,
+            max_depth: 16,
+            pos: (0., 1., 12.).into(),
+            look_at: (0., 1., 0.).into(),
+            up: (0., 1., 0.).into(),
+            defocus_angle: 0.,
+            focus_dist: 12.,
+            spectral: false,
+            lens: None,
+            filter: Filter::Mitchell { radius: 2. },
+            seed: None,
+        },
+        ViewportConfig::Fov { vertical_fov: 25.0 },
+    );
+
+    let gold = Color::new(1.0, 0.71, 0.29);
+    let red = Color::new(0.8, 0.1, 0.1);
+    let mut world = vec![Object::new(
+        Sphere::new((0., -1000., 0.), 1000.),
+        Lambertain::new(Color::new(0.5, 0.5, 0.5)),
+    )];
+    for i in 0..STEPS {
+        let roughness = i as f64 / (STEPS - 1) as f64;
+        let x = 2.2 * (i as f64 - (STEPS - 1) as f64 / 2.);
+        world.push(Object::new(
+            Sphere::new((x, 2.2, 0.), 0.9),
+            Principled::new(red, roughness, 0.),
+        ));
+        world.push(Object::new(
+            Sphere::new((x, 0.4, 0.), 0.9),
+            Principled::new(gold, roughness, 1.),
+        ));
+    }
+    let world = World::from(world);
+
+    let pixel_locator = PixelLocator::from_screen_and_camera(&screen, &camera);
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write!(
+        writer,
+        "P3\n{} {}\n255\n\n",
+        screen.width(),
+        screen.height()
+    )?;
+    let film = camera
+        .render_film(&world, &pixel_locator, &screen, &CancelToken::new(), |_| {})
+        .expect("render is never cancelled");
+    for pixel in film.pixels() {
+        writeln!(writer, "{}", pixel)?;
+    }
+    Ok(())
+}
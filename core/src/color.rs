@@ -1,7 +1,7 @@
 use std::{
     fmt::Display,
     iter::Sum,
-    ops::{Add, AddAssign, Mul},
+    ops::{Add, AddAssign, Mul, Sub},
 };
 
 use crate::{utils::Interval, vec3::Vec3};
@@ -22,6 +22,16 @@ impl Color {
         [self.0.x, self.0.y, self.0.z]
     }
 
+    /// Mean of the three channels
+    pub fn average(&self) -> f64 {
+        (self.0.x + self.0.y + self.0.z) / 3.
+    }
+
+    /// Linear interpolation from `self` at `t = 0` to `other` at `t = 1`
+    pub fn lerp(self, other: Self, t: f64) -> Self {
+        (1. - t) * self + t * other
+    }
+
     pub fn clamp<I: Interval<f64>>(self, interval: I) -> Self {
         Self(Vec3::new(
             interval.clamp(self.0.x),
@@ -81,6 +91,14 @@ impl Add for Color {
     }
 }
 
+impl Sub for Color {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Color(self.0 - rhs.0)
+    }
+}
+
 impl AddAssign for Color {
     fn add_assign(&mut self, rhs: Self) {
         self.0 += rhs.0
@@ -1,9 +1,12 @@
 pub mod color;
 
 pub mod materials;
+pub mod noise;
 pub mod ray;
 pub mod render;
 pub mod shapes;
+pub mod spectrum;
+pub mod texture;
 mod utils;
 pub mod vec3;
 pub mod world;
@@ -0,0 +1,125 @@
+use std::io;
+
+use rand::rngs::SmallRng;
+
+use crate::{color::Color, noise::Perlin, ray::Ray, shapes::Hit, vec3::Vec3};
+
+use super::{Material, Scatter};
+
+/// Step used to take the derivative of procedural height fields
+const BUMP_EPSILON: f64 = 1e-3;
+
+/// Wraps a material to perturb the shading normal of every hit before scattering.
+/// The geometric normal is left untouched.
+pub struct Bumped {
+    material: Material,
+    bump: Bump,
+}
+
+impl Bumped {
+    pub fn new<M: Into<Material>>(material: M, bump: Bump) -> Self {
+        Self {
+            material: material.into(),
+            bump,
+        }
+    }
+}
+
+impl Scatter for Bumped {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let mut hit = hit.clone();
+        let normal = self.bump.shading_normal(&hit);
+        // never bend the shading normal past the surface
+        if normal.dot(hit.geometric_normal) > 0. {
+            hit.normal = normal;
+        }
+        self.material.scatter(rng, ray, &hit)
+    }
+}
+
+pub enum Bump {
+    /// Tangent space normal map
+    NormalMap(NormalMap),
+    /// Procedural noise height field
+    Noise {
+        perlin: Perlin,
+        frequency: f64,
+        strength: f64,
+    },
+}
+
+impl Bump {
+    fn shading_normal(&self, hit: &Hit) -> Vec3 {
+        let n = hit.normal;
+        let t = hit.tangent;
+        let b = hit.bitangent();
+        match self {
+            Bump::NormalMap(map) => {
+                let local = map.at(hit.uv);
+                (local.x * t + local.y * b + local.z * n).normalize()
+            }
+            Bump::Noise {
+                perlin,
+                frequency,
+                strength,
+            } => {
+                let height = |p: Vec3| strength * perlin.noise(*frequency * p);
+                let h = height(hit.point);
+                let dhdt = (height(hit.point + BUMP_EPSILON * t) - h) / BUMP_EPSILON;
+                let dhdb = (height(hit.point + BUMP_EPSILON * b) - h) / BUMP_EPSILON;
+                (n - dhdt * t - dhdb * b).normalize()
+            }
+        }
+    }
+}
+
+/// Image of tangent space normals where red is the tangent, green the bitangent and blue the normal
+pub struct NormalMap {
+    width: usize,
+    height: usize,
+    normals: Vec<Vec3>,
+}
+
+impl NormalMap {
+    /// Normals are listed row by row starting at the top left of the image
+    pub fn new(width: usize, height: usize, normals: Vec<Vec3>) -> io::Result<Self> {
+        if width == 0 || height == 0 || width * height != normals.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "normal map of {}x{} does not match {} normals",
+                    width,
+                    height,
+                    normals.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            normals,
+        })
+    }
+
+    /// Loads a normal map image, colors are remapped from 0 to 1 into -1 to 1
+    #[cfg(feature = "image")]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> image::ImageResult<Self> {
+        let img = image::open(path)?.into_rgb32f();
+        let normals = img
+            .pixels()
+            .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64) * 2. + -1.)
+            .collect();
+        Ok(Self::new(
+            img.width() as usize,
+            img.height() as usize,
+            normals,
+        )?)
+    }
+
+    pub fn at(&self, (u, v): (f64, f64)) -> Vec3 {
+        let x = ((u.clamp(0., 1.) * self.width as f64) as usize).min(self.width - 1);
+        // images start at the top while v starts at the bottom
+        let y = (((1. - v.clamp(0., 1.)) * self.height as f64) as usize).min(self.height - 1);
+        self.normals[x + y * self.width]
+    }
+}
@@ -0,0 +1,72 @@
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{
+    color::{Color, WHITE},
+    ray::Ray,
+    shapes::Hit,
+};
+
+use super::{dielectric::reflectance, microfacet::Ggx, Material, Scatter};
+
+/// Thin dielectric layer, like varnish or lacquer, on top of any base material
+pub struct Coated {
+    base: Material,
+    /// Index of Refraction of the coating
+    ir: f64,
+    ggx: Option<Ggx>,
+    tint: Color,
+}
+
+impl Coated {
+    pub fn new<M: Into<Material>>(base: M, ir: f64) -> Self {
+        Self {
+            base: base.into(),
+            ir,
+            ggx: None,
+            tint: WHITE,
+        }
+    }
+
+    /// Roughness of the coating surface, 0 is perfectly smooth
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.ggx = (roughness > 0.).then(|| Ggx::from_roughness(roughness));
+        self
+    }
+
+    /// Color absorbed by light passing through the coating to the base
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+impl Scatter for Coated {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let unit_direction = ray.direction().normalize();
+        let cos_theta = (-unit_direction).dot(hit.normal).clamp(0., 1.);
+
+        if hit.is_front_face && reflectance(cos_theta, self.ir.recip()) > rng.gen::<f64>() {
+            return match &self.ggx {
+                Some(ggx) => {
+                    let wo = hit.normal.world_to_local(-unit_direction);
+                    let h = ggx.sample_visible_normal(rng, wo);
+                    let wi = (-wo).reflect(h);
+                    let scattered = hit
+                        .point
+                        .ray_timed(hit.normal.local_to_world(wi), ray.time());
+                    if wi.z <= 0. {
+                        return (scattered, None);
+                    }
+                    (scattered, Some(ggx.g2(wo, wi) / ggx.g1(wo) * WHITE))
+                }
+                None => {
+                    let direction = unit_direction.reflect(hit.normal);
+                    (hit.point.ray_timed(direction, ray.time()), Some(WHITE))
+                }
+            };
+        }
+
+        let (bounce, color) = self.base.scatter(rng, ray, hit);
+        (bounce, color.map(|c| c * self.tint))
+    }
+}
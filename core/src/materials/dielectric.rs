@@ -6,45 +6,139 @@ use crate::{
     shapes::Hit,
 };
 
-use super::Scatter;
+use super::{microfacet::Ggx, Scatter};
+
+/// Wavelengths in nanometers used to split the RGB channels when dispersing
+const CHANNEL_WAVELENGTHS: [f64; 3] = [650., 550., 450.];
+/// Wavelength in nanometers the index of refraction is specified at (sodium d-line)
+const REFERENCE_WAVELENGTH: f64 = 587.6;
 
 pub struct Dielectric {
     /// Index of Refraction
     ir: f64,
+    /// Beer-Lambert absorption coefficient per unit distance inside of the medium
+    absorption: Option<[f64; 3]>,
+    /// Microfacet distribution for frosted glass
+    ggx: Option<Ggx>,
+    /// Cauchy `B` coefficient in square micrometers
+    dispersion: f64,
 }
 
 impl Dielectric {
     /// New Dielectric with an index of refraction
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self {
+            ir,
+            absorption: None,
+            ggx: None,
+            dispersion: 0.,
+        }
+    }
+
+    /// Tints the medium, `color` is the fraction of light left after travelling one unit inside
+    pub fn with_absorption(mut self, color: Color) -> Self {
+        self.absorption = Some(color.into_arr().map(|c| -c.clamp(1e-6, 1.).ln()));
+        self
+    }
+
+    /// Frosts the surface, 0 is perfectly smooth
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.ggx = (roughness > 0.).then(|| Ggx::from_roughness(roughness));
+        self
+    }
+
+    /// Varies the index of refraction with wavelength (Cauchy's equation), typical glass is around 0.004
+    pub fn with_dispersion(mut self, cauchy_b: f64) -> Self {
+        self.dispersion = cauchy_b;
+        self
+    }
+
+    /// Index of refraction at the wavelength in nanometers
+    pub fn ir_at(&self, wavelength: f64) -> f64 {
+        // Cauchy's equation is in micrometers
+        let wavelength = wavelength / 1000.;
+        let reference = REFERENCE_WAVELENGTH / 1000.;
+        self.ir
+            + self.dispersion
+                * ((wavelength * wavelength).recip() - (reference * reference).recip())
+    }
+
+    fn attenuation(&self, ray: &Ray, hit: &Hit) -> Color {
+        match self.absorption {
+            // leaving the medium, the ray travelled through the inside since the last bounce
+            Some(absorption) if !hit.is_front_face => {
+                let distance = hit.t * ray.direction().length();
+                let [r, g, b] = absorption.map(|a| (-a * distance).exp());
+                Color::new(r, g, b)
+            }
+            _ => WHITE,
+        }
     }
 }
 
 impl Scatter for Dielectric {
     fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
-        let refraction_ratio = if hit.is_front_face {
-            self.ir.recip()
+        let mut attenuation = self.attenuation(ray, hit);
+        let mut channel = ray.channel();
+        let ir = if let Some(wavelength) = ray.wavelength() {
+            self.ir_at(wavelength)
+        } else if self.dispersion != 0. {
+            // the rest of the path follows a single channel so each one bends by its own amount
+            let channel = *channel.get_or_insert_with(|| {
+                let channel = rng.gen_range(0..3);
+                let mut mask = [0.; 3];
+                mask[channel] = 3.;
+                attenuation = attenuation * Color::new(mask[0], mask[1], mask[2]);
+                channel
+            });
+            self.ir_at(CHANNEL_WAVELENGTHS[channel])
         } else {
             self.ir
         };
+
+        let refraction_ratio = if hit.is_front_face { ir.recip() } else { ir };
         let unit_direction = ray.direction().normalize();
-        let cos_theta = (-unit_direction).dot(hit.normal).min(1.0);
+
+        let (normal, masking) = match &self.ggx {
+            Some(ggx) => {
+                let wo = hit.normal.world_to_local(-unit_direction);
+                let h = ggx.sample_visible_normal(rng, wo);
+                (hit.normal.local_to_world(h), Some((ggx, wo)))
+            }
+            None => (hit.normal, None),
+        };
+
+        let cos_theta = (-unit_direction).dot(normal).min(1.0);
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let reflectance = reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>();
 
-        let direction = if cannot_refract || reflectance {
-            unit_direction.reflect(hit.normal)
+        let (direction, reflected) = if cannot_refract || reflectance {
+            (unit_direction.reflect(normal), true)
         } else {
-            unit_direction.refract(hit.normal, refraction_ratio)
+            (unit_direction.refract(normal, refraction_ratio), false)
         };
+        let scattered = hit
+            .point
+            .ray_timed(direction, ray.time())
+            .with_channel(channel);
+
+        if let Some((ggx, wo)) = masking {
+            // rough surfaces can scatter to the wrong side of the macro surface
+            if (direction.dot(hit.geometric_normal) > 0.) != reflected {
+                return (scattered, None);
+            }
+            let wi = hit.normal.world_to_local(direction.normalize());
+            attenuation = attenuation * (ggx.g2(wo, wi) / ggx.g1(wo));
+        }
 
-        (hit.point.ray_timed(direction, ray.time()), Some(WHITE))
+        (scattered, Some(attenuation))
     }
 }
 
-fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+/// Schlick's approximation of the reflectance of a dielectric
+pub(crate) fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = (1. - ref_idx) / (1. + ref_idx);
     let r0 = r0 * r0;
     r0 + (1. - r0) * (1. - cosine).powi(5)
@@ -0,0 +1,48 @@
+use std::f64::consts::PI;
+
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{color::Color, ray::Ray, shapes::Hit, vec3::Vec3};
+
+use super::Scatter;
+
+/// Anisotropic phase function for participating media.
+/// `g` ranges from -1 (back scattering) through 0 (isotropic) to 1 (forward scattering).
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Self {
+        Self {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+
+    fn sample_cos_theta(&self, xi: f64) -> f64 {
+        let g = self.g;
+        if g.abs() < 1e-3 {
+            return 1. - 2. * xi;
+        }
+        let sqr_term = (1. - g * g) / (1. - g + 2. * g * xi);
+        ((1. + g * g - sqr_term * sqr_term) / (2. * g)).clamp(-1., 1.)
+    }
+}
+
+impl Scatter for HenyeyGreenstein {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let cos_theta = self.sample_cos_theta(rng.gen());
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * PI * rng.gen::<f64>();
+
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = ray.direction().normalize().local_to_world(local);
+
+        (
+            hit.point.ray_timed(direction, ray.time()),
+            Some(self.albedo),
+        )
+    }
+}
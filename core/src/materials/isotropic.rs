@@ -0,0 +1,25 @@
+use rand::rngs::SmallRng;
+
+use crate::{color::Color, ray::Ray, shapes::Hit, vec3::Vec3};
+
+use super::Scatter;
+
+/// Phase function that scatters uniformly in every direction, used for participating media.
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Scatter for Isotropic {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let scattered = hit
+            .point
+            .ray_timed(Vec3::random_unit_sphere(rng), ray.time());
+        (scattered, Some(self.albedo))
+    }
+}
@@ -27,6 +27,6 @@ impl Scatter for Lambertain {
             direction = hit.normal
         }
         let scattered = hit.point.ray_timed(direction, ray.time());
-        (scattered, Some(self.albedo.clone()))
+        (scattered, Some(self.albedo))
     }
 }
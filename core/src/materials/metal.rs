@@ -22,9 +22,9 @@ impl Scatter for Metal {
             reflected + self.fuzziness * Vec3::random_unit_sphere(rng),
             ray.time(),
         );
-        if scattered.direction().dot(hit.normal) < 0. {
+        if scattered.direction().dot(hit.geometric_normal) < 0. {
             return (scattered, None);
         }
-        (scattered, Some(self.albedo.clone()))
+        (scattered, Some(self.albedo))
     }
 }
@@ -0,0 +1,82 @@
+use std::f64::consts::PI;
+
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{
+    color::{Color, WHITE},
+    vec3::Vec3,
+};
+
+/// GGX (Trowbridge-Reitz) microfacet distribution.
+/// All directions are in the local shading frame where the normal is `+z`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// Perceptual roughness in the range of 0 (mirror) to 1 (fully rough)
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0., 1.);
+        Self {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    /// Smith masking helper function
+    fn lambda(&self, v: Vec3) -> f64 {
+        let cos2 = v.z * v.z;
+        if cos2 <= 0. {
+            return 0.;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        0.5 * (-1. + (1. + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// Fraction of microfacets visible from `v`
+    pub fn g1(&self, v: Vec3) -> f64 {
+        1. / (1. + self.lambda(v))
+    }
+
+    /// Height correlated masking-shadowing for the pair of directions
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Normal distribution function
+    pub fn d(&self, h: Vec3) -> f64 {
+        let a2 = self.alpha * self.alpha;
+        let denom = h.z * h.z * (a2 - 1.) + 1.;
+        a2 / (PI * denom * denom)
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `wo`
+    pub fn sample_visible_normal(&self, rng: &mut SmallRng, wo: Vec3) -> Vec3 {
+        let alpha = self.alpha;
+        let vh = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).normalize();
+
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0. {
+            Vec3::new(-vh.y, vh.x, 0.) / lensq.sqrt()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = rng.gen::<f64>().sqrt();
+        let phi = 2. * PI * rng.gen::<f64>();
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * p2;
+
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+        Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(0.)).normalize()
+    }
+}
+
+/// Schlick's approximation of the fresnel reflectance
+pub fn fresnel_schlick(f0: Color, cos_theta: f64) -> Color {
+    let f = (1. - cos_theta.clamp(0., 1.)).powi(5);
+    f0.lerp(WHITE, f)
+}
@@ -0,0 +1,37 @@
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{color::Color, ray::Ray, shapes::Hit, texture::Texture};
+
+use super::{Material, Scatter};
+
+/// Blends two materials, a weight of 0 is only `a` and 1 is only `b`
+pub struct Mix {
+    a: Material,
+    b: Material,
+    weight: Texture,
+}
+
+impl Mix {
+    pub fn new<A: Into<Material>, B: Into<Material>, W: Into<Texture>>(
+        a: A,
+        b: B,
+        weight: W,
+    ) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+            weight: weight.into(),
+        }
+    }
+}
+
+impl Scatter for Mix {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let weight = self.weight.value(hit.uv, hit.point);
+        if rng.gen::<f64>() < weight {
+            self.b.scatter(rng, ray, hit)
+        } else {
+            self.a.scatter(rng, ray, hit)
+        }
+    }
+}
@@ -2,11 +2,21 @@ use rand::rngs::SmallRng;
 
 use crate::{color::Color, ray::Ray, shapes::Hit};
 
-use self::{dielectric::Dielectric, lambertian::Lambertain, metal::Metal};
+use self::{
+    bump::Bumped, coated::Coated, dielectric::Dielectric, henyey_greenstein::HenyeyGreenstein,
+    isotropic::Isotropic, lambertian::Lambertain, metal::Metal, mix::Mix, principled::Principled,
+};
 
+pub mod bump;
+pub mod coated;
 pub mod dielectric;
+pub mod henyey_greenstein;
+pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
+pub mod microfacet;
+pub mod mix;
+pub mod principled;
 
 pub trait Scatter {
     /// Scatters the ray in the material
@@ -16,9 +26,15 @@ pub trait Scatter {
 }
 
 pub enum Material {
+    Bumped(Box<Bumped>),
+    Coated(Box<Coated>),
     Dielectric(Dielectric),
+    HenyeyGreenstein(HenyeyGreenstein),
+    Isotropic(Isotropic),
     Lambertain(Lambertain),
     Metal(Metal),
+    Mix(Box<Mix>),
+    Principled(Principled),
     Custom(Box<dyn Scatter + Send + Sync>),
 }
 
@@ -32,8 +48,29 @@ macro_rules! mat_from {
     };
 }
 mat_from!(Dielectric);
+mat_from!(HenyeyGreenstein);
+mat_from!(Isotropic);
 mat_from!(Lambertain);
 mat_from!(Metal);
+mat_from!(Principled);
+
+impl From<Bumped> for Material {
+    fn from(value: Bumped) -> Self {
+        Self::Bumped(Box::new(value))
+    }
+}
+
+impl From<Coated> for Material {
+    fn from(value: Coated) -> Self {
+        Self::Coated(Box::new(value))
+    }
+}
+
+impl From<Mix> for Material {
+    fn from(value: Mix) -> Self {
+        Self::Mix(Box::new(value))
+    }
+}
 
 impl From<Box<dyn Scatter + Send + Sync>> for Material {
     fn from(value: Box<dyn Scatter + Send + Sync>) -> Self {
@@ -41,12 +78,36 @@ impl From<Box<dyn Scatter + Send + Sync>> for Material {
     }
 }
 
+impl Material {
+    /// Number identifying the kind of material
+    pub fn kind_id(&self) -> u32 {
+        match self {
+            Material::Bumped(_) => 0,
+            Material::Coated(_) => 1,
+            Material::Dielectric(_) => 2,
+            Material::HenyeyGreenstein(_) => 3,
+            Material::Isotropic(_) => 4,
+            Material::Lambertain(_) => 5,
+            Material::Metal(_) => 6,
+            Material::Mix(_) => 7,
+            Material::Principled(_) => 8,
+            Material::Custom(_) => 9,
+        }
+    }
+}
+
 impl Scatter for Material {
     fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
         match self {
+            Material::Bumped(m) => m.scatter(rng, ray, hit),
+            Material::Coated(m) => m.scatter(rng, ray, hit),
             Material::Dielectric(m) => m.scatter(rng, ray, hit),
+            Material::HenyeyGreenstein(m) => m.scatter(rng, ray, hit),
+            Material::Isotropic(m) => m.scatter(rng, ray, hit),
             Material::Lambertain(m) => m.scatter(rng, ray, hit),
             Material::Metal(m) => m.scatter(rng, ray, hit),
+            Material::Mix(m) => m.scatter(rng, ray, hit),
+            Material::Principled(m) => m.scatter(rng, ray, hit),
             Material::Custom(m) => m.scatter(rng, ray, hit),
         }
     }
@@ -0,0 +1,113 @@
+use approx::ulps_eq;
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{
+    color::{Color, WHITE},
+    ray::Ray,
+    shapes::Hit,
+    vec3::{Vec3, ZERO},
+};
+
+use super::{
+    microfacet::{fresnel_schlick, Ggx},
+    Scatter,
+};
+
+/// Fresnel reflectance at normal incidence of the clearcoat layer (IOR 1.5)
+const CLEARCOAT_F0: f64 = 0.04;
+
+/// Disney style principled BSDF made of a diffuse base, a GGX specular lobe and an optional clearcoat.
+/// Each lobe is importance sampled and the lobe weights are energy conserving.
+pub struct Principled {
+    base_color: Color,
+    metallic: f64,
+    specular: f64,
+    ggx: Ggx,
+    clearcoat: f64,
+    clearcoat_ggx: Ggx,
+}
+
+impl Principled {
+    pub fn new(base_color: Color, roughness: f64, metallic: f64) -> Self {
+        Self {
+            base_color,
+            metallic: metallic.clamp(0., 1.),
+            specular: 0.5,
+            ggx: Ggx::from_roughness(roughness),
+            clearcoat: 0.,
+            clearcoat_ggx: Ggx::from_roughness(0.03),
+        }
+    }
+
+    /// Specular reflectance of dielectrics, 0.5 maps to an IOR of 1.5
+    pub fn with_specular(mut self, specular: f64) -> Self {
+        self.specular = specular.clamp(0., 1.);
+        self
+    }
+
+    /// Adds a clear glossy layer on top of the base
+    pub fn with_clearcoat(mut self, clearcoat: f64, roughness: f64) -> Self {
+        self.clearcoat = clearcoat.clamp(0., 1.);
+        self.clearcoat_ggx = Ggx::from_roughness(roughness);
+        self
+    }
+
+    fn f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular;
+        Color::new(dielectric, dielectric, dielectric).lerp(self.base_color, self.metallic)
+    }
+
+    /// Samples a reflection off of the microfacets returning the local incoming direction, the microfacet normal and `G2 / G1`
+    fn sample_reflection(ggx: &Ggx, rng: &mut SmallRng, wo: Vec3) -> Option<(Vec3, Vec3, f64)> {
+        let h = ggx.sample_visible_normal(rng, wo);
+        let wi = (-wo).reflect(h);
+        if wi.z <= 0. {
+            return None;
+        }
+        Some((wi, h, ggx.g2(wo, wi) / ggx.g1(wo)))
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(&self, rng: &mut SmallRng, ray: &Ray, hit: &Hit) -> (Ray, Option<Color>) {
+        let normal = hit.normal;
+        let mut wo = normal.world_to_local(-ray.direction().normalize());
+        wo.z = wo.z.max(1e-4);
+
+        let reflect = |wi: Vec3| hit.point.ray_timed(normal.local_to_world(wi), ray.time());
+
+        if self.clearcoat > 0. {
+            let fc = self.clearcoat
+                * fresnel_schlick(Color::new(CLEARCOAT_F0, CLEARCOAT_F0, CLEARCOAT_F0), wo.z)
+                    .average();
+            if rng.gen::<f64>() < fc {
+                return match Self::sample_reflection(&self.clearcoat_ggx, rng, wo) {
+                    Some((wi, _, g)) => (reflect(wi), Some(g * WHITE)),
+                    None => (ray.clone(), None),
+                };
+            }
+        }
+
+        let f0 = self.f0();
+        let fresnel = fresnel_schlick(f0, wo.z);
+        let p_specular = fresnel.average().max(self.metallic).clamp(0.01, 1.);
+
+        if rng.gen::<f64>() < p_specular {
+            return match Self::sample_reflection(&self.ggx, rng, wo) {
+                Some((wi, h, g)) => {
+                    let weight = fresnel_schlick(f0, wo.dot(h)) * (g / p_specular);
+                    (reflect(wi), Some(weight))
+                }
+                None => (ray.clone(), None),
+            };
+        }
+
+        let mut direction = normal + Vec3::random_unit_sphere(rng);
+        if ulps_eq!(direction, ZERO) {
+            direction = normal
+        }
+        let weight =
+            (1. - self.metallic) * self.base_color * (WHITE - fresnel) * (1. - p_specular).recip();
+        (hit.point.ray_timed(direction, ray.time()), Some(weight))
+    }
+}
@@ -0,0 +1,96 @@
+use rand::{seq::SliceRandom, Rng};
+
+use crate::vec3::{Vec3, ZERO};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| Vec3::random_unit_sphere(rng))
+            .collect();
+        Self {
+            ranvec,
+            perm_x: Self::generate_perm(rng),
+            perm_y: Self::generate_perm(rng),
+            perm_z: Self::generate_perm(rng),
+        }
+    }
+
+    /// Noise value at the point in the range of -1 to 1
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut c = [[[ZERO; 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.ranvec[self.perm_x[Self::wrap(i + di as i64)]
+                        ^ self.perm_y[Self::wrap(j + dj as i64)]
+                        ^ self.perm_z[Self::wrap(k + dk as i64)]];
+                }
+            }
+        }
+
+        Self::trilinear_interp(&c, u, v, w)
+    }
+
+    /// Sum of multiple octaves of noise, always positive
+    pub fn turbulence(&self, p: Vec3, depth: u32) -> f64 {
+        let mut accum = 0.;
+        let mut temp_p = p;
+        let mut weight = 1.;
+        for _ in 0..depth {
+            accum += weight * self.noise(temp_p);
+            weight *= 0.5;
+            temp_p = temp_p * 2.;
+        }
+        accum.abs()
+    }
+
+    fn wrap(i: i64) -> usize {
+        (i & (POINT_COUNT as i64 - 1)) as usize
+    }
+
+    fn generate_perm<R: Rng>(rng: &mut R) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        perm.shuffle(rng);
+        perm
+    }
+
+    fn trilinear_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        // Hermite smoothing
+        let uu = u * u * (3. - 2. * u);
+        let vv = v * v * (3. - 2. * v);
+        let ww = w * w * (3. - 2. * w);
+
+        let mut accum = 0.;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (i, j, k) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(u - i, v - j, w - k);
+                    accum += (i * uu + (1. - i) * (1. - uu))
+                        * (j * vv + (1. - j) * (1. - vv))
+                        * (k * ww + (1. - k) * (1. - ww))
+                        * corner.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+}
@@ -5,6 +5,10 @@ pub struct Ray {
     origin: Vec3,
     direction: Vec3,
     time: f64,
+    /// Wavelength in nanometers carried by the ray when rendering spectrally
+    wavelength: Option<f64>,
+    /// RGB channel the path follows after hitting a dispersive material
+    channel: Option<usize>,
 }
 
 impl Ray {
@@ -13,6 +17,8 @@ impl Ray {
             origin,
             direction,
             time: 0.,
+            wavelength: None,
+            channel: None,
         }
     }
 
@@ -21,9 +27,21 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
+            channel: None,
         }
     }
 
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: Option<usize>) -> Self {
+        self.channel = channel;
+        self
+    }
+
     pub fn origin(&self) -> Vec3 {
         self.origin
     }
@@ -36,6 +54,14 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn channel(&self) -> Option<usize> {
+        self.channel
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
     }
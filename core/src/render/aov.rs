@@ -0,0 +1,251 @@
+use crate::{
+    color::{Color, BLACK},
+    vec3::Vec3,
+};
+
+/// Arbitrary output variable, an extra render pass taken from the first hit of the camera rays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum Aov {
+    /// Distance from the camera, infinite where nothing was hit
+    Depth,
+    /// World position
+    Position,
+    /// Shading normal in world space
+    Normal,
+    /// Surface color without lighting, the sky color where nothing was hit
+    Albedo,
+    /// Index of the object in the world, -1 where nothing was hit
+    ObjectId,
+    /// Material ID of the object, -1 where nothing was hit
+    MaterialId,
+    /// Screen movement in pixels over the shutter interval, only for planar projections
+    Motion,
+    /// Light reaching the camera after a single bounce
+    Direct,
+    /// Light reaching the camera after more than one bounce
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Depth,
+        Aov::Position,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Motion,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Motion => "motion",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    /// Names of the channels, matching the leading values of `value`
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Position | Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Motion => &["X", "Y"],
+            Aov::Albedo | Aov::Direct | Aov::Indirect => &["R", "G", "B"],
+        }
+    }
+
+    pub fn value(&self, pixel: &AovPixel) -> [f32; 3] {
+        let vec = |v: Vec3| [v.x as f32, v.y as f32, v.z as f32];
+        let color = |c: Color| c.into_arr().map(|c| c as f32);
+        let id = |id: Option<usize>| [id.map(|id| id as f32).unwrap_or(-1.), 0., 0.];
+        match self {
+            Aov::Depth => [pixel.depth as f32, 0., 0.],
+            Aov::Position => vec(pixel.position),
+            Aov::Normal => vec(pixel.normal),
+            Aov::Albedo => color(pixel.albedo),
+            Aov::ObjectId => id(pixel.object_id),
+            Aov::MaterialId => id(pixel.material_id.map(|id| id as usize)),
+            Aov::Motion => [pixel.motion.0 as f32, pixel.motion.1 as f32, 0.],
+            Aov::Direct => color(pixel.direct),
+            Aov::Indirect => color(pixel.indirect),
+        }
+    }
+}
+
+/// First hit of a single camera ray
+#[derive(Debug, Clone)]
+pub(crate) struct AovSample {
+    /// Position of the sample from the pixel center
+    pub offset: (f64, f64),
+    pub hit: Option<AovHit>,
+    pub albedo: Color,
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AovHit {
+    pub depth: f64,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub object_id: Option<usize>,
+    pub material_id: Option<u32>,
+    pub motion: (f64, f64),
+}
+
+/// Render pass values of a pixel
+#[derive(Debug, Clone)]
+pub struct AovPixel {
+    pub depth: f64,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub object_id: Option<usize>,
+    pub material_id: Option<u32>,
+    pub motion: (f64, f64),
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+impl AovPixel {
+    /// Pixel where nothing was hit
+    pub fn empty() -> Self {
+        Self {
+            depth: f64::INFINITY,
+            position: Vec3::new(0., 0., 0.),
+            normal: Vec3::new(0., 0., 0.),
+            albedo: BLACK,
+            object_id: None,
+            material_id: None,
+            motion: (0., 0.),
+            direct: BLACK,
+            indirect: BLACK,
+        }
+    }
+
+    /// Averages the samples, IDs can't be blended so they come from the sample closest to the center
+    pub(crate) fn from_samples(samples: &[AovSample]) -> Self {
+        let count = samples.len().max(1) as f64;
+        let mut pixel = Self::empty();
+        pixel.depth = 0.;
+        let mut hits = 0;
+        for sample in samples {
+            pixel.albedo += sample.albedo;
+            pixel.direct += sample.direct;
+            pixel.indirect += sample.indirect;
+            if let Some(hit) = &sample.hit {
+                hits += 1;
+                pixel.depth += hit.depth;
+                pixel.position += hit.position;
+                pixel.normal += hit.normal;
+                pixel.motion.0 += hit.motion.0;
+                pixel.motion.1 += hit.motion.1;
+            }
+        }
+        pixel.albedo = count.recip() * pixel.albedo;
+        pixel.direct = count.recip() * pixel.direct;
+        pixel.indirect = count.recip() * pixel.indirect;
+
+        if hits == 0 {
+            pixel.depth = f64::INFINITY;
+        } else {
+            let hits = hits as f64;
+            pixel.depth /= hits;
+            pixel.position = pixel.position / hits;
+            if pixel.normal.length_squared() > 0. {
+                pixel.normal = pixel.normal.normalize();
+            }
+            pixel.motion = (pixel.motion.0 / hits, pixel.motion.1 / hits);
+        }
+
+        let center = samples.iter().min_by(|a, b| {
+            let da = a.offset.0.powi(2) + a.offset.1.powi(2);
+            let db = b.offset.0.powi(2) + b.offset.1.powi(2);
+            da.total_cmp(&db)
+        });
+        if let Some(hit) = center.and_then(|s| s.hit.as_ref()) {
+            pixel.object_id = hit.object_id;
+            pixel.material_id = hit.material_id;
+        }
+        pixel
+    }
+}
+
+/// Render passes of a whole image
+pub struct AovImage {
+    pub width: u64,
+    pub height: u64,
+    pub pixels: Vec<AovPixel>,
+}
+
+impl AovImage {
+    /// Values of one pass row by row
+    pub fn layer(&self, aov: Aov) -> Vec<[f32; 3]> {
+        self.pixels.iter().map(|p| aov.value(p)).collect()
+    }
+
+    /// Writes the passes, and the beauty image when given, as layers of a single OpenEXR file
+    #[cfg(feature = "exr")]
+    pub fn write_exr<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: W,
+        beauty: Option<&[Color]>,
+        aovs: &[Aov],
+    ) -> exr::error::UnitResult {
+        use exr::prelude::*;
+
+        let size = Vec2(self.width as usize, self.height as usize);
+        let channels = |names: &[&str], values: Vec<[f32; 3]>| {
+            let channels = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let samples = values.iter().map(|v| v[i]).collect();
+                    AnyChannel::new(*name, FlatSamples::F32(samples))
+                })
+                .collect();
+            AnyChannels::sort(channels)
+        };
+
+        let mut layers = Vec::new();
+        if let Some(beauty) = beauty {
+            let beauty = beauty
+                .iter()
+                .map(|c| c.into_arr().map(|c| c as f32))
+                .collect();
+            layers.push(Layer::new(
+                size,
+                LayerAttributes::named("beauty"),
+                Encoding::SMALL_LOSSLESS,
+                channels(&["R", "G", "B"], beauty),
+            ));
+        }
+        for aov in aovs {
+            layers.push(Layer::new(
+                size,
+                LayerAttributes::named(aov.name()),
+                Encoding::SMALL_LOSSLESS,
+                channels(aov.channels(), self.layer(*aov)),
+            ));
+        }
+
+        Image::from_layers(
+            ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+            layers,
+        )
+        .write()
+        .to_buffered(writer)
+    }
+}
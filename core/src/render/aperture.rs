@@ -0,0 +1,56 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{texture::ImageTexture, vec3::Vec3};
+
+/// Attempts at finding an open spot in an image aperture before falling back to the center
+const MASK_ATTEMPTS: usize = 64;
+
+/// Shape of the lens opening which gives out of focus highlights (bokeh) their shape
+#[derive(Debug)]
+pub enum Aperture {
+    Disk,
+    /// Regular polygon formed by the aperture blades
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    /// Grayscale mask over the unit square where brighter areas let more light through
+    Image(ImageTexture),
+}
+
+impl Aperture {
+    /// Random point on the aperture within the unit square from -1 to 1
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+        match self {
+            Aperture::Disk => {
+                let p = Vec3::random_in_unit_disk(rng);
+                (p.x, p.y)
+            }
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let blade = rng.gen_range(0..blades) as f64;
+                let step = 2. * PI / blades as f64;
+                let a0 = rotation.to_radians() + blade * step;
+                let a1 = a0 + step;
+
+                // uniform point in the triangle made by the center and the blade edge
+                let (mut u, mut v) = (rng.gen::<f64>(), rng.gen::<f64>());
+                if u + v > 1. {
+                    (u, v) = (1. - u, 1. - v);
+                }
+                (u * a0.cos() + v * a1.cos(), u * a0.sin() + v * a1.sin())
+            }
+            Aperture::Image(mask) => {
+                for _ in 0..MASK_ATTEMPTS {
+                    let (u, v) = (rng.gen::<f64>(), rng.gen::<f64>());
+                    if rng.gen::<f64>() < mask.at((u, v)) {
+                        return (2. * u - 1., 2. * v - 1.);
+                    }
+                }
+                (0., 0.)
+            }
+        }
+    }
+}
@@ -1,18 +1,32 @@
+use std::time::Instant;
+
 use rand::{rngs::SmallRng, Rng, SeedableRng};
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    color::{Color, BLACK, SKY_BLUE, WHITE},
+    color::{Color, BLACK, WHITE},
     ray::Ray,
+    spectrum,
     vec3::Vec3,
+    world::Surface,
 };
 
 use super::{
+    aov::{AovHit, AovPixel, AovSample},
+    aperture::Aperture,
+    cancel::CancelToken,
+    film::{Film, Splat},
+    filter::Filter,
+    lens::Lens,
     screen::Screen,
+    stats::{RenderCounters, RenderStats},
     viewport::{Viewport, ViewportConfig},
     PixelLocator, World,
 };
 
+/// Width and height of the tiles rendered in parallel
+const TILE_SIZE: u64 = 16;
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
@@ -31,6 +45,18 @@ pub struct CameraConfig {
     pub defocus_angle: f64,
     /// Distance from camera pos point to plane of perfect focus
     pub focus_dist: f64,
+    /// Trace a single wavelength per sample instead of RGB
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spectral: bool,
+    /// Physical lens that overrides the field of view, defocus angle and focus distance
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lens: Option<Lens>,
+    /// Pixel reconstruction filter
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filter: Filter,
+    /// Seed of the random samples, the same seed renders the same image. Random when not set
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seed: Option<u64>,
 }
 
 pub struct Defocus {
@@ -43,36 +69,118 @@ pub struct Camera {
     config: CameraConfig,
     viewport_config: ViewportConfig,
     focal_length: f64,
+    aperture: Aperture,
+    aovs: bool,
+    seed: u64,
+    counters: RenderCounters,
+}
+
+/// Sample of a pixel at an offset from its center
+struct PixelSample {
+    offset: (f64, f64),
+    color: Color,
+    aov: Option<AovSample>,
+    rays: u32,
+    hits: u32,
+}
+
+/// Result of tracing a camera ray
+struct Traced {
+    color: Color,
+    /// Number of surfaces the ray bounced off before reaching the sky
+    bounces: u32,
+    /// Rays cast while tracing, one per bounce
+    rays: u32,
+    /// Surfaces hit while tracing, including when the path was cut off at the max depth
+    hits: u32,
+    /// Point and surface of the first hit
+    first_hit: Option<(Vec3, Option<Surface>, Color)>,
 }
 
 impl Camera {
     pub fn new(config: CameraConfig, viewport_config: ViewportConfig) -> Self {
         let focal_length = (config.pos - config.look_at).length();
+        let seed = config.seed.unwrap_or_else(rand::random);
         Self {
             config,
             viewport_config,
             focal_length,
+            aperture: Aperture::Disk,
+            aovs: false,
+            seed,
+            counters: RenderCounters::new(),
         }
     }
 
+    /// Also collects the render passes of each pixel into the splats
+    pub fn with_aovs(mut self) -> Self {
+        self.aovs = true;
+        self
+    }
+
+    /// Shape of the lens opening used for depth of field
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
     pub fn focal_length(&self) -> f64 {
         self.focal_length
     }
 
+    /// Seed of the random samples, picked at random when the config has none
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Totals of everything rendered with this camera so far
+    pub fn stats(&self) -> RenderStats {
+        self.counters.stats(self.seed)
+    }
+
     pub fn config(&self) -> &CameraConfig {
         &self.config
     }
 
-    pub fn viewport(&self, screen: &Screen) -> (Viewport, Defocus) {
-        let (height, width) = self
-            .viewport_config
-            .get_dims(screen, self.config.focus_dist);
+    /// Distance from the camera pos to the plane of perfect focus
+    pub fn focus_dist(&self) -> f64 {
+        match &self.config.lens {
+            Some(lens) => {
+                let forward = (self.config.look_at - self.config.pos).normalize();
+                lens.focus_dist(self.config.pos, forward)
+            }
+            None => self.config.focus_dist,
+        }
+    }
+
+    fn has_defocus(&self) -> bool {
+        self.config.lens.is_some() || self.config.defocus_angle > 0.
+    }
+
+    pub fn viewport_config(&self) -> &ViewportConfig {
+        &self.viewport_config
+    }
+
+    /// Camera space unit vectors: right, up and backwards (away from the look at point)
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
         let w = (self.config.pos - self.config.look_at).normalize();
         let u = self.config.up.cross(w).normalize();
         let v = w.cross(u);
+        (u, v, w)
+    }
 
-        let defocus_radius =
-            self.config.focus_dist * (self.config.defocus_angle / 2.).to_radians().tan();
+    pub fn viewport(&self, screen: &Screen) -> (Viewport, Defocus) {
+        let focus_dist = self.focus_dist();
+        let (height, width) = match &self.config.lens {
+            Some(lens) => lens.get_dims(screen, focus_dist),
+            None => self.viewport_config.get_dims(screen, focus_dist),
+        };
+        let (u, v, w) = self.basis();
+
+        let defocus_radius = match &self.config.lens {
+            Some(lens) => lens.aperture_radius(),
+            None => focus_dist * (self.config.defocus_angle / 2.).to_radians().tan(),
+        };
         let defocus = Defocus {
             disk_u: u * defocus_radius,
             disk_v: v * defocus_radius,
@@ -84,55 +192,282 @@ impl Camera {
         let viewport = Viewport {
             u,
             v,
-            upper_left: self.config.pos - (self.config.focus_dist * w) - u / 2. - v / 2.,
+            upper_left: self.config.pos - (focus_dist * w) - u / 2. - v / 2.,
         };
 
         (viewport, defocus)
     }
 
-    pub fn get_color(&self, world: &World, pixel_locator: &PixelLocator, pixel_loc: Vec3) -> Color {
-        let defocus_angle = self.config.defocus_angle;
-        let pos = self.config.pos;
-        let max_depth = self.config.max_depth;
-        let pixel = BLACK
-            + (0..self.config.samples_per_pixel)
-                .into_par_iter()
-                .map_init(
-                    || SmallRng::from_entropy(),
-                    |rng, _| {
-                        // Adds antialising
-                        let px = -0.5 + rng.gen::<f64>();
-                        let py = -0.5 + rng.gen::<f64>();
-                        let pixel_sample = pixel_locator.adjust_pixel_loc(pixel_loc, px, py);
-                        let ray_origin = if defocus_angle <= 0. {
-                            pos
-                        } else {
-                            let p = Vec3::random_in_unit_disk(rng);
-                            pixel_locator.defocus_pixel(pos, p.x, p.y)
-                        };
-                        let ray_direction = pixel_sample - ray_origin;
-                        let ray_time = rng.gen::<f64>();
-
-                        let ray = ray_origin.ray_timed(ray_direction, ray_time);
-                        Self::ray_color(rng, ray, world, max_depth)
+    /// Renders the image in parallel tiles, checking `cancel` before every tile.
+    /// `on_tile` is called with the number of pixels after each finished tile.
+    /// Returns `None` when the render was cancelled.
+    pub fn render_film(
+        &self,
+        world: &World,
+        pixel_locator: &PixelLocator,
+        screen: &Screen,
+        cancel: &CancelToken,
+        on_tile: impl Fn(u64) + Sync,
+    ) -> Option<Film> {
+        self.render_progressive(world, pixel_locator, screen, 1, cancel, on_tile, |_, _| {})
+    }
+
+    /// Like `render_film` but splits the samples per pixel evenly over `passes` passes of the whole
+    /// image, `on_pass` gets the number of the finished pass from 1 and the film so far.
+    /// The render passes of the image come from the last pass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_progressive(
+        &self,
+        world: &World,
+        pixel_locator: &PixelLocator,
+        screen: &Screen,
+        passes: u32,
+        cancel: &CancelToken,
+        on_tile: impl Fn(u64) + Sync,
+        mut on_pass: impl FnMut(u32, &Film),
+    ) -> Option<Film> {
+        let start = Instant::now();
+        let spp = self.config.samples_per_pixel;
+        let passes = passes.clamp(1, spp.max(1));
+        let tiles: Vec<(u64, u64)> = (0..screen.height().div_ceil(TILE_SIZE))
+            .flat_map(|ty| (0..screen.width().div_ceil(TILE_SIZE)).map(move |tx| (tx, ty)))
+            .collect();
+
+        let mut film = Film::new(screen);
+        let mut first_sample = 0;
+        for pass in 0..passes {
+            // the remainder goes to the first passes
+            let samples = spp / passes + u32::from(pass < spp % passes);
+            let pass_film = tiles
+                .par_iter()
+                .fold(
+                    || Film::new(screen),
+                    |mut film, (tx, ty)| {
+                        if cancel.is_cancelled() {
+                            return film;
+                        }
+                        let xs = tx * TILE_SIZE..((tx + 1) * TILE_SIZE).min(screen.width());
+                        let ys = ty * TILE_SIZE..((ty + 1) * TILE_SIZE).min(screen.height());
+                        for y in ys.clone() {
+                            for x in xs.clone() {
+                                film.add(&self.splat(
+                                    world,
+                                    pixel_locator,
+                                    x,
+                                    y,
+                                    first_sample,
+                                    samples,
+                                ));
+                            }
+                        }
+                        on_tile(xs.count() as u64 * ys.count() as u64);
+                        film
                     },
                 )
-                .sum();
+                .reduce(|| Film::new(screen), Film::merge);
+            if cancel.is_cancelled() {
+                self.counters
+                    .add_render(screen.width() * screen.height(), start.elapsed());
+                return None;
+            }
+            film = film.merge(pass_film);
+            first_sample += samples;
+            on_pass(pass + 1, &film);
+        }
+        self.counters
+            .add_render(screen.width() * screen.height(), start.elapsed());
+        Some(film)
+    }
 
-        let pixel = (self.config.samples_per_pixel as f64).recip() * pixel;
-        pixel.clamp(0.0..0.9999)
+    /// Traces the samples of a pixel and weights them for every neighbour within the filter radius
+    pub fn get_splat(&self, world: &World, pixel_locator: &PixelLocator, x: u64, y: u64) -> Splat {
+        self.splat(world, pixel_locator, x, y, 0, self.config.samples_per_pixel)
     }
 
-    fn ray_color(rng: &mut SmallRng, ray: Ray, world: &World, max_depth: u32) -> Color {
+    fn splat(
+        &self,
+        world: &World,
+        pixel_locator: &PixelLocator,
+        x: u64,
+        y: u64,
+        first_sample: u32,
+        sample_count: u32,
+    ) -> Splat {
+        let has_defocus = self.has_defocus();
+        let aperture = &self.aperture;
+        let max_depth = self.config.max_depth;
+        let spectral = self.config.spectral;
+        let pixel_loc = pixel_locator.pixel_center(x, y);
+        let aovs = self.aovs;
+        let seed = self.seed;
+        let samples: Vec<PixelSample> = (first_sample..first_sample + sample_count)
+            .into_par_iter()
+            .map(|sample| {
+                let rng = &mut SmallRng::seed_from_u64(sample_seed(seed, x, y, sample));
+                // Adds antialising
+                let px = -0.5 + rng.gen::<f64>();
+                let py = -0.5 + rng.gen::<f64>();
+                let pixel_sample = pixel_locator.adjust_pixel_loc(pixel_loc, px, py);
+                let lens = if has_defocus {
+                    aperture.sample(rng)
+                } else {
+                    (0., 0.)
+                };
+                let Some((ray_origin, ray_direction)) = pixel_locator.ray(pixel_sample, lens)
+                else {
+                    // outside of the projection
+                    let aov = aovs.then_some(AovSample {
+                        offset: (px, py),
+                        hit: None,
+                        albedo: BLACK,
+                        direct: BLACK,
+                        indirect: BLACK,
+                    });
+                    return PixelSample {
+                        offset: (px, py),
+                        color: BLACK,
+                        aov,
+                        rays: 0,
+                        hits: 0,
+                    };
+                };
+                let ray_time = rng.gen::<f64>();
+
+                let wavelength = spectral.then(|| spectrum::sample_wavelength(rng));
+
+                let ray = ray_origin
+                    .ray_timed(ray_direction, ray_time)
+                    .with_wavelength(wavelength);
+                let traced = Self::ray_color(rng, ray.clone(), world, max_depth);
+                let aov =
+                    aovs.then(|| Self::aov_sample(world, pixel_locator, &ray, (px, py), &traced));
+                PixelSample {
+                    offset: (px, py),
+                    color: traced.color,
+                    aov,
+                    rays: traced.rays,
+                    hits: traced.hits,
+                }
+            })
+            .collect();
+        let (rays, hits) = samples.iter().fold((0, 0), |(rays, hits), s| {
+            (rays + s.rays as u64, hits + s.hits as u64)
+        });
+        self.counters.add_samples(sample_count as u64, rays, hits);
+
+        let filter = &self.config.filter;
+        let reach = (filter.radius() - 0.5).ceil().max(0.) as i64;
+        let mut splat = Splat::new(x, y, reach);
+        if aovs {
+            let aov_samples: Vec<AovSample> =
+                samples.iter().filter_map(|s| s.aov.clone()).collect();
+            splat.aov = Some(AovPixel::from_samples(&aov_samples));
+        }
+        for PixelSample {
+            offset: (px, py),
+            color,
+            ..
+        } in samples
+        {
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let weight = filter.weight(px - dx as f64, py - dy as f64);
+                    if weight != 0. {
+                        splat.add(dx, dy, color, weight);
+                    }
+                }
+            }
+        }
+        splat
+    }
+
+    fn aov_sample(
+        world: &World,
+        pixel_locator: &PixelLocator,
+        ray: &Ray,
+        offset: (f64, f64),
+        traced: &Traced,
+    ) -> AovSample {
+        let (direct, indirect) = match traced.bounces {
+            1 => (traced.color, BLACK),
+            0 => (BLACK, BLACK),
+            _ => (BLACK, traced.color),
+        };
+        let Some((point, surface, albedo)) = &traced.first_hit else {
+            return AovSample {
+                offset,
+                hit: None,
+                albedo: world.background().color(ray.direction()),
+                direct,
+                indirect,
+            };
+        };
+
+        let motion = surface
+            .as_ref()
+            .and_then(|s| {
+                let start = pixel_locator.project(*point - ray.time() * s.velocity)?;
+                let end = pixel_locator.project(*point + (1. - ray.time()) * s.velocity)?;
+                Some((end.0 - start.0, end.1 - start.1))
+            })
+            .unwrap_or((0., 0.));
+        AovSample {
+            offset,
+            hit: Some(AovHit {
+                depth: (*point - ray.origin()).length(),
+                position: *point,
+                normal: surface
+                    .as_ref()
+                    .map(|s| s.normal)
+                    .unwrap_or(Vec3::new(0., 0., 0.)),
+                object_id: surface.as_ref().map(|s| s.object_id),
+                material_id: surface.as_ref().map(|s| s.material_id),
+                motion,
+            }),
+            albedo: *albedo,
+            direct,
+            indirect,
+        }
+    }
+
+    fn ray_color(rng: &mut SmallRng, ray: Ray, world: &World, max_depth: u32) -> Traced {
         let mut stack = vec![(ray, WHITE, 0)];
         let mut output = BLACK;
+        let mut bounces = 0;
+        let mut rays = 0;
+        let mut hits = 0;
+        let mut first_hit = None;
         while let Some((cur, attenuation, depth)) = stack.pop() {
+            let wavelength = cur.wavelength();
+            rays += 1;
             if let Some(cast) = world.cast(rng, &cur, 0.001..f64::INFINITY) {
-                let new_att = cast.color.map(|c| attenuation * c).unwrap_or(BLACK);
-                stack.push((cast.bounce, new_att, depth + 1))
+                hits += 1;
+                if depth == 0 {
+                    first_hit = Some((
+                        cast.bounce.origin(),
+                        cast.surface.clone(),
+                        cast.color.unwrap_or(BLACK),
+                    ));
+                }
+                let new_att = cast
+                    .color
+                    .map(|c| attenuation * Self::at_wavelength(c, wavelength))
+                    .unwrap_or(BLACK);
+                let channel = cast.bounce.channel().or(cur.channel());
+                let bounce = cast
+                    .bounce
+                    .with_wavelength(wavelength)
+                    .with_channel(channel);
+                stack.push((bounce, new_att, depth + 1))
             } else {
                 // ray stopped bouncing
-                output = attenuation * Self::render_skybox(&cur);
+                let sky =
+                    Self::at_wavelength(world.background().color(cur.direction()), wavelength);
+                output = attenuation * sky;
+                if let Some(wavelength) = wavelength {
+                    output = output * spectrum::to_rgb(wavelength);
+                }
+                bounces = depth;
                 break;
             }
             if depth >= max_depth {
@@ -140,12 +475,30 @@ impl Camera {
                 break;
             }
         }
-        output
+        Traced {
+            color: output,
+            bounces,
+            rays,
+            hits,
+            first_hit,
+        }
     }
 
-    fn render_skybox(ray: &Ray) -> Color {
-        let direction = ray.direction();
-        let gradiant = 0.5 * (direction.y + 1.0);
-        Color::from((1.0 - gradiant) * WHITE + gradiant * SKY_BLUE)
+    /// Spectral value of the color as a gray color when the ray carries a wavelength
+    fn at_wavelength(color: Color, wavelength: Option<f64>) -> Color {
+        match wavelength {
+            Some(wavelength) => {
+                let s = spectrum::reflectance(color, wavelength);
+                Color::new(s, s, s)
+            }
+            None => color,
+        }
     }
 }
+
+/// Seed of one sample of a pixel, the rng scrambles it so neighbouring values are unrelated
+fn sample_seed(seed: u64, x: u64, y: u64, sample: u32) -> u64 {
+    seed ^ x.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ y.wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (sample as u64).wrapping_mul(0x1656_67B1_9E37_79F9)
+}
@@ -0,0 +1,111 @@
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    color::{Color, BLACK},
+    vec3::Vec3,
+};
+
+use super::aov::AovImage;
+
+/// Passes of the filter, each doubles the spacing of the taps
+const ITERATIONS: u32 = 5;
+/// B3 spline kernel of the wavelet transform
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+/// How different two colors may be, relative to their brightness, before they stop blending
+const COLOR_SIGMA: f64 = 1.0;
+const ALBEDO_SIGMA: f64 = 0.3;
+/// Power of the normal similarity, higher keeps sharper creases
+const NORMAL_POWER: i32 = 64;
+
+/// Edge avoiding à-trous wavelet filter guided by the albedo and normal render passes.
+/// Pixels only blend with neighbours of a similar color, albedo and normal.
+#[derive(Debug, Clone)]
+pub struct Denoiser {
+    /// Blend between the noisy image at 0 and the fully filtered image at 1
+    pub strength: f64,
+}
+
+impl Denoiser {
+    pub fn new(strength: f64) -> Self {
+        Self {
+            strength: strength.clamp(0., 1.),
+        }
+    }
+
+    /// Filters the row by row pixels, the render passes have to be of the same image
+    pub fn denoise(&self, pixels: &[Color], aovs: &AovImage) -> Vec<Color> {
+        assert_eq!(pixels.len(), aovs.pixels.len(), "render pass size mismatch");
+        if self.strength <= 0. {
+            return pixels.to_vec();
+        }
+        let width = aovs.width as i64;
+        let height = aovs.height as i64;
+
+        let albedo: Vec<Color> = aovs.pixels.iter().map(|p| p.albedo).collect();
+        let mut color = pixels.to_vec();
+
+        for i in 0..ITERATIONS {
+            let step = 1 << i;
+            let filtered = (0..color.len())
+                .into_par_iter()
+                .map(|p| {
+                    let (x, y) = (p as i64 % width, p as i64 / width);
+                    let center = &aovs.pixels[p];
+                    let mut sum = BLACK;
+                    let mut total = 0.;
+                    for (ky, hy) in KERNEL.iter().enumerate() {
+                        for (kx, hx) in KERNEL.iter().enumerate() {
+                            let qx = x + (kx as i64 - 2) * step;
+                            let qy = y + (ky as i64 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                                continue;
+                            }
+                            let q = (qy * width + qx) as usize;
+                            let other = &aovs.pixels[q];
+                            // never blend surfaces with the background
+                            if center.depth.is_finite() != other.depth.is_finite() {
+                                continue;
+                            }
+
+                            // relative to the brightness so dark areas keep their detail
+                            let color_dist = distance_squared(color[p], color[q])
+                                / (0.1 + color[p].average()).powi(2);
+                            let albedo_dist = distance_squared(albedo[p], albedo[q]);
+                            let weight = hx
+                                * hy
+                                * (-color_dist / (COLOR_SIGMA * COLOR_SIGMA)).exp()
+                                * (-albedo_dist / (ALBEDO_SIGMA * ALBEDO_SIGMA)).exp()
+                                * normal_weight(center.normal, other.normal);
+                            sum += weight * color[q];
+                            total += weight;
+                        }
+                    }
+                    if total > 0. {
+                        total.recip() * sum
+                    } else {
+                        color[p]
+                    }
+                })
+                .collect();
+            color = filtered;
+        }
+
+        pixels
+            .iter()
+            .zip(color)
+            .map(|(noisy, denoised)| noisy.lerp(denoised, self.strength))
+            .collect()
+    }
+}
+
+fn distance_squared(a: Color, b: Color) -> f64 {
+    (a - b).into_arr().iter().map(|d| d * d).sum()
+}
+
+fn normal_weight(a: Vec3, b: Vec3) -> f64 {
+    // fog and missed pixels have no normal
+    if a.length_squared() == 0. || b.length_squared() == 0. {
+        return 1.;
+    }
+    a.dot(b).max(0.).powi(NORMAL_POWER)
+}
@@ -0,0 +1,136 @@
+use crate::color::{Color, BLACK};
+
+use super::{
+    aov::{AovImage, AovPixel},
+    screen::Screen,
+};
+
+/// Filtered samples taken for one pixel, spread over the pixels around it
+pub struct Splat {
+    x: i64,
+    y: i64,
+    /// Number of neighbouring pixels reached on each side
+    reach: i64,
+    /// Weighted color sum and weight sum row by row over the square around the pixel
+    pixels: Vec<(Color, f64)>,
+    /// Render passes of the pixel itself
+    pub(crate) aov: Option<AovPixel>,
+}
+
+impl Splat {
+    pub(crate) fn new(x: u64, y: u64, reach: i64) -> Self {
+        let size = (2 * reach + 1) as usize;
+        Self {
+            x: x as i64,
+            y: y as i64,
+            reach,
+            pixels: vec![(BLACK, 0.); size * size],
+            aov: None,
+        }
+    }
+
+    /// Adds a sample to the neighbour offset by `dx`, `dy` pixels
+    pub(crate) fn add(&mut self, dx: i64, dy: i64, color: Color, weight: f64) {
+        let size = 2 * self.reach + 1;
+        let (sum, total) = &mut self.pixels[((dy + self.reach) * size + dx + self.reach) as usize];
+        *sum += weight * color;
+        *total += weight;
+    }
+}
+
+/// Accumulates splats into the final image
+pub struct Film {
+    width: u64,
+    height: u64,
+    pixels: Vec<(Color, f64)>,
+    aovs: Vec<Option<AovPixel>>,
+}
+
+impl Film {
+    pub fn new(screen: &Screen) -> Self {
+        Self {
+            width: screen.width(),
+            height: screen.height(),
+            pixels: vec![(BLACK, 0.); (screen.width() * screen.height()) as usize],
+            aovs: Vec::new(),
+        }
+    }
+
+    /// Adds the splat, parts outside of the image are dropped
+    pub fn add(&mut self, splat: &Splat) {
+        if let Some(aov) = &splat.aov {
+            if self.aovs.is_empty() {
+                self.aovs = vec![None; self.pixels.len()];
+            }
+            self.aovs[(splat.y as u64 * self.width + splat.x as u64) as usize] = Some(aov.clone());
+        }
+        let size = 2 * splat.reach + 1;
+        for (i, (color, weight)) in splat.pixels.iter().enumerate() {
+            let x = splat.x + i as i64 % size - splat.reach;
+            let y = splat.y + i as i64 / size - splat.reach;
+            if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+                continue;
+            }
+            let (sum, total) = &mut self.pixels[(y as u64 * self.width + x as u64) as usize];
+            *sum += *color;
+            *total += weight;
+        }
+    }
+
+    /// Combines films of the same size rendered separately
+    pub fn merge(mut self, other: Film) -> Film {
+        for ((sum, total), (other_sum, other_total)) in self.pixels.iter_mut().zip(other.pixels) {
+            *sum += other_sum;
+            *total += other_total;
+        }
+        if self.aovs.is_empty() {
+            self.aovs = other.aovs;
+        } else {
+            for (aov, other_aov) in self.aovs.iter_mut().zip(other.aovs) {
+                if other_aov.is_some() {
+                    *aov = other_aov;
+                }
+            }
+        }
+        self
+    }
+
+    /// Normalized pixel colors row by row, clamped for output
+    pub fn pixels(&self) -> Vec<Color> {
+        self.radiance()
+            .into_iter()
+            .map(|c| c.clamp(0.0..0.9999))
+            .collect()
+    }
+
+    /// Normalized pixel colors row by row without clamping, for post-processing
+    pub fn radiance(&self) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|(sum, total)| {
+                if total.abs() < 1e-12 {
+                    BLACK
+                } else {
+                    total.recip() * *sum
+                }
+            })
+            .collect()
+    }
+
+    /// Render passes when the camera collected them
+    pub fn aovs(&self) -> Option<AovImage> {
+        if self.aovs.is_empty() {
+            return None;
+        }
+        let pixels = self
+            .aovs
+            .iter()
+            .map(|aov| aov.clone().unwrap_or_else(AovPixel::empty))
+            .collect();
+        Some(AovImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        })
+    }
+}
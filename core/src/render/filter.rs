@@ -0,0 +1,82 @@
+use std::f64::consts::PI;
+
+/// Pixel reconstruction filter, `radius` is measured in pixels from the pixel center.
+/// Samples are weighted by the filter for every pixel within the radius.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum Filter {
+    /// Equal weight, a radius of 0.5 averages the samples within each pixel
+    Box { radius: f64 },
+    /// Weight falls off linearly to the radius
+    Tent { radius: f64 },
+    /// Bell curve, larger `alpha` gives a narrower falloff
+    Gaussian { radius: f64, alpha: f64 },
+    /// Mitchell-Netravali cubic with the recommended B = C = 1/3
+    Mitchell { radius: f64 },
+    /// Windowed sinc, sharpest but may ring around hard edges
+    Lanczos { radius: f64 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    /// Weight of a sample offset by `dx`, `dy` pixels from the pixel center
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        let x = x.abs();
+        if x > radius {
+            return 0.;
+        }
+        match *self {
+            Filter::Box { .. } => 1.,
+            Filter::Tent { .. } => radius - x,
+            Filter::Gaussian { alpha, .. } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.)
+            }
+            Filter::Mitchell { .. } => mitchell(2. * x / radius, 1. / 3., 1. / 3.),
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    let value = if x < 1. {
+        (12. - 9. * b - 6. * c) * x.powi(3) + (-18. + 12. * b + 6. * c) * x.powi(2) + (6. - 2. * b)
+    } else if x < 2. {
+        (-b - 6. * c) * x.powi(3)
+            + (6. * b + 30. * c) * x.powi(2)
+            + (-12. * b - 48. * c) * x
+            + (8. * b + 24. * c)
+    } else {
+        0.
+    };
+    value / 6.
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::vec3::Vec3;
+
+use super::screen::Screen;
+
+/// Physical thin lens description, scene units are treated as meters.
+/// Replaces the viewport field of view and the defocus angle of the camera.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Lens {
+    /// Focal length in millimeters
+    pub focal_length: f64,
+    /// Aperture f-number, the aperture diameter is the focal length divided by this
+    pub f_stop: f64,
+    /// Sensor height in millimeters, 24 for a full frame 35mm sensor
+    pub sensor_height: f64,
+    pub focus: Focus,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum Focus {
+    /// Distance from the camera to the plane of perfect focus
+    Distance(f64),
+    /// Autofocus so the point is sharp
+    Point(Vec3),
+}
+
+impl Lens {
+    /// Vertical field of view in degrees
+    pub fn vertical_fov(&self) -> f64 {
+        2. * (self.sensor_height / (2. * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// Radius of the aperture in scene units
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / (2. * self.f_stop) / 1000.
+    }
+
+    /// Distance along the view direction to the plane of perfect focus
+    pub fn focus_dist(&self, pos: Vec3, forward: Vec3) -> f64 {
+        match self.focus {
+            Focus::Distance(dist) => dist,
+            Focus::Point(point) => (point - pos).dot(forward),
+        }
+    }
+
+    pub fn get_dims(&self, screen: &Screen, focus_dist: f64) -> (f64, f64) {
+        let h = (self.vertical_fov().to_radians() / 2.).tan();
+        let height = 2. * h * focus_dist;
+        (
+            height,
+            height * (screen.width() as f64) / (screen.height() as f64),
+        )
+    }
+}
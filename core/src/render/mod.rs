@@ -1,5 +1,15 @@
+pub mod aov;
+pub mod aperture;
 pub mod camera;
+pub mod cancel;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod lens;
+pub mod post;
 pub mod screen;
+pub mod stats;
+pub mod stereo;
 pub mod viewport;
 
 use crate::{vec3::Vec3, world::World};
@@ -7,6 +17,8 @@ use crate::{vec3::Vec3, world::World};
 use self::{
     camera::{Camera, Defocus},
     screen::Screen,
+    stereo::{Eye, Stereo},
+    viewport::{Viewport, ViewportConfig},
 };
 
 pub struct PixelLocator {
@@ -14,6 +26,16 @@ pub struct PixelLocator {
     delta_v: Vec3,
     upper_left_loc: Vec3,
     defocus: Defocus,
+    viewport: Viewport,
+    projection: ViewportConfig,
+    pos: Vec3,
+    /// Right, up and backwards unit vectors of the camera
+    basis: (Vec3, Vec3, Vec3),
+    aspect: f64,
+    focus_dist: f64,
+    /// Distance of the eye from the camera pos along the right vector, 0 for a mono camera
+    eye_offset: f64,
+    convergence: f64,
 }
 
 impl PixelLocator {
@@ -26,9 +48,85 @@ impl PixelLocator {
             delta_v,
             upper_left_loc: viewport.upper_left + 0.5 * (delta_u + delta_v),
             defocus,
+            viewport,
+            projection: camera.viewport_config().clone(),
+            pos: camera.config().pos,
+            basis: camera.basis(),
+            aspect: (screen.width() as f64) / (screen.height() as f64),
+            focus_dist: camera.focus_dist(),
+            eye_offset: 0.,
+            convergence: f64::INFINITY,
         }
     }
 
+    /// Locator for one eye of a stereo pair, `screen` is the size of a single eye image
+    pub fn for_eye(screen: &Screen, camera: &Camera, stereo: &Stereo, eye: Eye) -> Self {
+        Self {
+            eye_offset: stereo.eye_offset(eye),
+            convergence: stereo.convergence,
+            ..Self::from_screen_and_camera(screen, camera)
+        }
+    }
+
+    /// Origin and direction of the ray through a point on the viewport.
+    /// `lens` is the position on the aperture from -1 to 1 used for depth of field.
+    pub fn ray(&self, pixel_sample: Vec3, lens: (f64, f64)) -> Option<(Vec3, Vec3)> {
+        let (u, v, w) = self.basis;
+        match self.projection {
+            ViewportConfig::Orthographic { .. } => {
+                // move the sample back onto the plane of the camera
+                let origin = pixel_sample + (self.pos - pixel_sample).dot(w) * w;
+                Some((origin + self.eye_offset * u, -w))
+            }
+            ref projection if !projection.is_planar() => {
+                let rel = pixel_sample - self.viewport.upper_left;
+                let s = rel.dot(self.viewport.u) / self.viewport.u.length_squared();
+                let t = rel.dot(self.viewport.v) / self.viewport.v.length_squared();
+                let d = projection.direction(s, t, self.aspect)?;
+                let d = (d.x * u + d.y * v - d.z * w).normalize();
+                // omni-directional stereo: the eye sits on a circle, sideways to each direction.
+                // The offset fades out towards the poles to avoid swirling.
+                let origin = self.pos + self.eye_offset * d.cross(v);
+                if self.convergence.is_finite() {
+                    Some((origin, self.pos + self.convergence * d - origin))
+                } else {
+                    Some((origin, d))
+                }
+            }
+            _ => {
+                let eye = self.pos + self.eye_offset * u;
+                let origin = self.defocus_pixel(eye, lens.0, lens.1);
+                // shift the viewport so both eyes line up at the convergence distance
+                let shift = self.eye_offset * (1. - self.focus_dist / self.convergence);
+                Some((origin, pixel_sample + shift * u - origin))
+            }
+        }
+    }
+
+    /// Position of a point on the screen in pixels, only available for planar projections
+    pub fn project(&self, point: Vec3) -> Option<(f64, f64)> {
+        let (_, _, w) = self.basis;
+        let on_plane = match self.projection {
+            ViewportConfig::Orthographic { .. } => {
+                point - (point - self.viewport.upper_left).dot(w) * w
+            }
+            ref projection if projection.is_planar() => {
+                let rel = point - self.pos;
+                let depth = -rel.dot(w);
+                if depth <= 0. {
+                    return None;
+                }
+                self.pos + (self.focus_dist / depth) * rel
+            }
+            _ => return None,
+        };
+        let rel = on_plane - self.upper_left_loc;
+        Some((
+            rel.dot(self.delta_u) / self.delta_u.length_squared(),
+            rel.dot(self.delta_v) / self.delta_v.length_squared(),
+        ))
+    }
+
     pub fn adjust_pixel_loc(&self, pixel_loc: Vec3, dx: f64, dy: f64) -> Vec3 {
         pixel_loc + (dx * self.delta_u) + (dy * self.delta_v)
     }
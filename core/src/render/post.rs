@@ -0,0 +1,341 @@
+use std::{fs, io, path::Path};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    color::{Color, BLACK},
+    spectrum,
+};
+
+use super::screen::Screen;
+
+/// Post-processing effect applied to the linear framebuffer before it is clamped for output
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum Effect {
+    /// Glow around pixels brighter than `threshold`, `radius` is a fraction of the image height
+    Bloom {
+        threshold: f64,
+        intensity: f64,
+        radius: f64,
+    },
+    /// Darkens the corners, 0 leaves the image as is and 1 makes the corners black
+    Vignette {
+        strength: f64,
+    },
+    /// Splits the red and blue channels towards the edges, `strength` is a fraction of the image size
+    ChromaticAberration {
+        strength: f64,
+    },
+    /// Random film grain, stronger in the mid tones
+    Grain {
+        amount: f64,
+    },
+    ColorGrade(ColorGrade),
+}
+
+/// Exposure, white balance, contrast, saturation and an optional lookup table, in that order
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ColorGrade {
+    /// Brightness change in stops
+    pub exposure: f64,
+    /// Color temperature of the light in kelvin that is corrected to white, 6500 is neutral
+    pub temperature: f64,
+    /// Pivots around middle gray, 1 is unchanged
+    pub contrast: f64,
+    /// 0 is grayscale, 1 is unchanged
+    pub saturation: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lut: Option<Lut>,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        Self {
+            exposure: 0.,
+            temperature: 6500.,
+            contrast: 1.,
+            saturation: 1.,
+            lut: None,
+        }
+    }
+}
+
+/// 3D color lookup table over the 0 to 1 RGB cube
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "LutTable")
+)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Lut {
+    /// Entries along each axis, at least 2
+    size: usize,
+    /// Output red, green and blue of every entry with red changing fastest, then green, then blue
+    table: Vec<f64>,
+}
+
+/// Unchecked table that is only turned into a [`Lut`] through [`Lut::new`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct LutTable {
+    size: usize,
+    table: Vec<f64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<LutTable> for Lut {
+    type Error = io::Error;
+
+    fn try_from(value: LutTable) -> io::Result<Self> {
+        Self::new(value.size, value.table)
+    }
+}
+
+impl Lut {
+    pub fn new(size: usize, table: Vec<f64>) -> io::Result<Self> {
+        if size < 2 || table.len() != size * size * size * 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "lut table size does not match",
+            ));
+        }
+        Ok(Self { size, table })
+    }
+
+    /// Loads a `.cube` file as used by most grading tools
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let invalid =
+            |e: &dyn std::error::Error| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+        let mut size = 0;
+        let mut table = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if let Some(s) = line.strip_prefix("LUT_3D_SIZE") {
+                size = s.trim().parse().map_err(|e| invalid(&e))?;
+            } else if line.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+                for v in line.split_whitespace() {
+                    table.push(v.parse().map_err(|e| invalid(&e))?);
+                }
+            }
+            // comments, titles and domain settings are ignored
+        }
+        Self::new(size, table)
+    }
+
+    pub fn at(&self, color: Color) -> Color {
+        let n = self.size - 1;
+        let [r, g, b] = color.clamp(0.0..=1.0).into_arr().map(|c| c * n as f64);
+        let (r0, g0, b0) = (
+            (r as usize).min(n - 1),
+            (g as usize).min(n - 1),
+            (b as usize).min(n - 1),
+        );
+        let (fr, fg, fb) = (r - r0 as f64, g - g0 as f64, b - b0 as f64);
+
+        // trilinear blend of the surrounding entries
+        let mut out = BLACK;
+        for (db, wb) in [(0, 1. - fb), (1, fb)] {
+            for (dg, wg) in [(0, 1. - fg), (1, fg)] {
+                for (dr, wr) in [(0, 1. - fr), (1, fr)] {
+                    let i =
+                        3 * ((r0 + dr) + (g0 + dg) * self.size + (b0 + db) * self.size * self.size);
+                    let entry = Color::new(self.table[i], self.table[i + 1], self.table[i + 2]);
+                    out += (wr * wg * wb) * entry;
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Effect {
+    /// `seed` makes random effects like grain reproducible
+    pub fn apply(&self, pixels: &[Color], screen: &Screen, seed: u64) -> Vec<Color> {
+        match self {
+            Effect::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => bloom(pixels, screen, *threshold, *intensity, *radius),
+            Effect::Vignette { strength } => {
+                let (width, height) = (screen.width() as usize, screen.height() as usize);
+                pixels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let (x, y) = centered(i % width, i / width, width, height);
+                        // natural cos^4 falloff of a lens with the edge of the height at 45 degrees
+                        let cos4 = (1. + x * x + y * y).powi(2).recip();
+                        (1. - strength * (1. - cos4)) * *c
+                    })
+                    .collect()
+            }
+            Effect::ChromaticAberration { strength } => {
+                let (width, height) = (screen.width() as usize, screen.height() as usize);
+                (0..pixels.len())
+                    .into_par_iter()
+                    .map(|i| {
+                        let (x, y) = (i % width, i / width);
+                        let shifted = |scale: f64| {
+                            let cx = width as f64 / 2.;
+                            let cy = height as f64 / 2.;
+                            let sx = cx + (x as f64 + 0.5 - cx) * scale - 0.5;
+                            let sy = cy + (y as f64 + 0.5 - cy) * scale - 0.5;
+                            sample(pixels, width, height, sx, sy)
+                        };
+                        let [r, _, _] = shifted(1. + strength).into_arr();
+                        let [_, g, _] = pixels[i].into_arr();
+                        let [_, _, b] = shifted(1. - strength).into_arr();
+                        Color::new(r, g, b)
+                    })
+                    .collect()
+            }
+            Effect::Grain { amount } => {
+                let mut rng = SmallRng::seed_from_u64(seed);
+                pixels
+                    .iter()
+                    .map(|c| {
+                        let luma = c.average().clamp(0., 1.);
+                        let noise =
+                            rng.gen_range::<f64, _>(-1.0..1.0) * amount * 4. * luma * (1. - luma);
+                        (1. + noise) * *c
+                    })
+                    .collect()
+            }
+            Effect::ColorGrade(grade) => color_grade(pixels, grade),
+        }
+    }
+}
+
+/// Runs the effects in order
+pub fn post_process(
+    effects: &[Effect],
+    pixels: Vec<Color>,
+    screen: &Screen,
+    seed: u64,
+) -> Vec<Color> {
+    effects
+        .iter()
+        .fold(pixels, |pixels, effect| effect.apply(&pixels, screen, seed))
+}
+
+fn color_grade(pixels: &[Color], grade: &ColorGrade) -> Vec<Color> {
+    const MIDDLE_GRAY: f64 = 0.18;
+    let exposure = 2f64.powf(grade.exposure);
+    let balance = white_balance(grade.temperature);
+    pixels
+        .into_par_iter()
+        .map(|c| {
+            let [r, g, b] = (exposure * *c * balance)
+                .into_arr()
+                .map(|v| MIDDLE_GRAY * (v.max(0.) / MIDDLE_GRAY).powf(grade.contrast));
+            let c = Color::new(r, g, b);
+            let luma = luminance(c);
+            let c = Color::new(luma, luma, luma).lerp(c, grade.saturation);
+            match &grade.lut {
+                Some(lut) => lut.at(c),
+                None => c,
+            }
+        })
+        .collect()
+}
+
+fn luminance(c: Color) -> f64 {
+    let [r, g, b] = c.into_arr();
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Multiplier that makes light of the temperature white
+fn white_balance(temperature: f64) -> Color {
+    let reference = spectrum::blackbody_rgb(6500.).into_arr();
+    let light = spectrum::blackbody_rgb(temperature).into_arr();
+    let [r, g, b] = [0, 1, 2].map(|i| reference[i] / light[i].max(1e-6));
+    // keep the brightness of the green channel
+    Color::new(r / g, 1., b / g)
+}
+
+/// Pixel position from -1 to 1 along the height, keeping the aspect ratio
+fn centered(x: usize, y: usize, width: usize, height: usize) -> (f64, f64) {
+    let half = height as f64 / 2.;
+    (
+        (x as f64 + 0.5 - width as f64 / 2.) / half,
+        (y as f64 + 0.5 - half) / half,
+    )
+}
+
+/// Bilinear lookup clamped to the edges of the image
+fn sample(pixels: &[Color], width: usize, height: usize, x: f64, y: f64) -> Color {
+    let x = x.clamp(0., (width - 1) as f64);
+    let y = y.clamp(0., (height - 1) as f64);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let top = pixels[y0 * width + x0].lerp(pixels[y0 * width + x1], fx);
+    let bottom = pixels[y1 * width + x0].lerp(pixels[y1 * width + x1], fx);
+    top.lerp(bottom, fy)
+}
+
+fn bloom(
+    pixels: &[Color],
+    screen: &Screen,
+    threshold: f64,
+    intensity: f64,
+    radius: f64,
+) -> Vec<Color> {
+    let (width, height) = (screen.width() as usize, screen.height() as usize);
+    let bright: Vec<Color> = pixels
+        .iter()
+        .map(|c| {
+            let luma = luminance(*c);
+            if luma <= threshold {
+                BLACK
+            } else {
+                ((luma - threshold) / luma) * *c
+            }
+        })
+        .collect();
+
+    // separable gaussian blur, rows then columns
+    let sigma = (radius * height as f64).max(0.5);
+    let reach = (3. * sigma).ceil() as i64;
+    let kernel: Vec<f64> = (-reach..=reach)
+        .map(|i| (-(i * i) as f64 / (2. * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    let kernel: Vec<f64> = kernel.iter().map(|k| k / total).collect();
+    let blur = |src: &[Color], horizontal: bool| -> Vec<Color> {
+        (0..src.len())
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = ((i % width) as i64, (i / width) as i64);
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let offset = k as i64 - reach;
+                        let (sx, sy) = if horizontal {
+                            ((x + offset).clamp(0, width as i64 - 1), y)
+                        } else {
+                            (x, (y + offset).clamp(0, height as i64 - 1))
+                        };
+                        *weight * src[(sy * width as i64 + sx) as usize]
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+    let glow = blur(&blur(&bright, true), false);
+
+    pixels
+        .iter()
+        .zip(glow)
+        .map(|(c, g)| *c + intensity * g)
+        .collect()
+}
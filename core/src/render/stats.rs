@@ -0,0 +1,79 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Counters shared by every render thread, added to once per pixel
+#[derive(Debug, Default)]
+pub struct RenderCounters {
+    rays: AtomicU64,
+    /// Surfaces hit by all paths together
+    bounces: AtomicU64,
+    samples: AtomicU64,
+    pixels: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl RenderCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn add_samples(&self, samples: u64, rays: u64, bounces: u64) {
+        self.samples.fetch_add(samples, Ordering::Relaxed);
+        self.rays.fetch_add(rays, Ordering::Relaxed);
+        self.bounces.fetch_add(bounces, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_render(&self, pixels: u64, took: Duration) {
+        self.pixels.fetch_add(pixels, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(took.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, seed: u64) -> RenderStats {
+        let rays = self.rays.load(Ordering::Relaxed);
+        let bounces = self.bounces.load(Ordering::Relaxed);
+        let samples = self.samples.load(Ordering::Relaxed);
+        let pixels = self.pixels.load(Ordering::Relaxed);
+        let time = Duration::from_nanos(self.nanos.load(Ordering::Relaxed));
+        let per = |count: u64, of: f64| if of == 0. { 0. } else { count as f64 / of };
+        RenderStats {
+            time,
+            rays,
+            rays_per_second: per(rays, time.as_secs_f64()),
+            average_depth: per(bounces, samples as f64),
+            samples_per_pixel: per(samples, pixels as f64),
+            seed,
+        }
+    }
+}
+
+/// Summary of everything rendered with a camera
+#[derive(Debug, Clone)]
+pub struct RenderStats {
+    pub time: Duration,
+    pub rays: u64,
+    pub rays_per_second: f64,
+    /// Surfaces hit per camera ray on average
+    pub average_depth: f64,
+    /// Samples actually taken, less than configured when the render was cancelled
+    pub samples_per_pixel: f64,
+    pub seed: u64,
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} rays in {:.3}s ({:.2} Mrays/s), average depth {:.2}, {:.1} samples per pixel, seed {}",
+            self.rays,
+            self.time.as_secs_f64(),
+            self.rays_per_second / 1e6,
+            self.average_depth,
+            self.samples_per_pixel,
+            self.seed,
+        )
+    }
+}
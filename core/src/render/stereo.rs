@@ -0,0 +1,93 @@
+use std::io;
+
+use crate::color::Color;
+
+use super::screen::Screen;
+
+/// Renders a separate image for each eye and packs them into one frame.
+/// With an equirectangular projection this gives omni-directional stereo (ODS) for VR viewers.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Stereo {
+    /// Interpupillary distance in scene units
+    pub ipd: f64,
+    /// Distance from the camera where both eyes line up (zero parallax)
+    pub convergence: f64,
+    pub layout: StereoLayout,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum StereoLayout {
+    /// Left eye on the left half and right eye on the right half
+    SideBySide,
+    /// Left eye on the top half and right eye on the bottom half
+    TopBottom,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Stereo {
+    pub fn new(ipd: f64, convergence: f64, layout: StereoLayout) -> io::Result<Self> {
+        // infinity is fine and gives parallel eyes
+        if convergence.is_nan() || convergence <= 0. {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "stereo convergence {} has to be greater than 0",
+                    convergence
+                ),
+            ));
+        }
+        Ok(Self {
+            ipd,
+            convergence,
+            layout,
+        })
+    }
+
+    /// Signed distance of the eye from the camera position along the camera right vector
+    pub fn eye_offset(&self, eye: Eye) -> f64 {
+        match eye {
+            Eye::Left => -self.ipd / 2.,
+            Eye::Right => self.ipd / 2.,
+        }
+    }
+
+    /// Size of the image rendered for a single eye
+    pub fn eye_screen(&self, screen: &Screen) -> Screen {
+        match self.layout {
+            StereoLayout::SideBySide => Screen::new(screen.width / 2, screen.height),
+            StereoLayout::TopBottom => Screen::new(screen.width, screen.height / 2),
+        }
+    }
+
+    /// Size of the packed frame holding both eyes, can be a pixel smaller than the requested screen
+    pub fn frame_screen(&self, eye_screen: &Screen) -> Screen {
+        match self.layout {
+            StereoLayout::SideBySide => Screen::new(eye_screen.width * 2, eye_screen.height),
+            StereoLayout::TopBottom => Screen::new(eye_screen.width, eye_screen.height * 2),
+        }
+    }
+
+    /// Packs the row by row pixels of both eyes, each the size of `eye_screen`, into one frame
+    pub fn combine(&self, eye_screen: &Screen, left: Vec<Color>, right: Vec<Color>) -> Vec<Color> {
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let width = eye_screen.width as usize;
+                left.chunks(width)
+                    .zip(right.chunks(width))
+                    .flat_map(|(l, r)| l.iter().chain(r))
+                    .copied()
+                    .collect()
+            }
+            StereoLayout::TopBottom => left.into_iter().chain(right).collect(),
+        }
+    }
+}
@@ -1,29 +1,112 @@
+use std::f64::consts::PI;
+
 use crate::vec3::Vec3;
 
 use super::screen::Screen;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub enum ViewportConfig {
-    Standard { height: f64 },
-    Fov { vertical_fov: f64 },
+    Standard {
+        height: f64,
+    },
+    Fov {
+        vertical_fov: f64,
+    },
+    /// Parallel rays, `height` is the size of the view in scene units
+    Orthographic {
+        height: f64,
+    },
+    /// Full 360° by 180° latitude longitude panorama
+    Equirectangular,
+    /// Circular fisheye fitting the height of the screen
+    Fisheye {
+        fov: f64,
+        mapping: FisheyeMapping,
+    },
+    /// Panorama wrapping horizontally around the camera with straight vertical lines
+    Cylindrical {
+        horizontal_fov: f64,
+        vertical_fov: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum FisheyeMapping {
+    /// Distance from the center is proportional to the angle
+    Equidistant,
+    /// Preserves area, distance from the center is proportional to the sine of half the angle
+    Equisolid,
 }
 
 impl ViewportConfig {
     pub fn get_dims(&self, screen: &Screen, focus_dist: f64) -> (f64, f64) {
+        let aspect = (screen.width() as f64) / (screen.height() as f64);
         match self {
-            Self::Standard { height } => (
-                *height,
-                height * (screen.width() as f64) / (screen.height() as f64),
-            ),
+            Self::Standard { height } | Self::Orthographic { height } => (*height, height * aspect),
             Self::Fov { vertical_fov } => {
                 let h = (vertical_fov.to_radians() / 2.).tan();
                 let height = 2. * h * focus_dist;
 
-                (
-                    height,
-                    height * (screen.width() as f64) / (screen.height() as f64),
-                )
+                (height, height * aspect)
+            }
+            // the plane is only used to locate pixels, directions come from `direction`
+            _ => (focus_dist, focus_dist * aspect),
+        }
+    }
+
+    /// Whether rays start at the camera and go through the viewport plane
+    pub fn is_planar(&self) -> bool {
+        matches!(
+            self,
+            Self::Standard { .. } | Self::Fov { .. } | Self::Orthographic { .. }
+        )
+    }
+
+    /// Ray direction in camera space (`x` right, `y` up, `z` forward) for the position on the screen
+    /// from 0 to 1 starting at the upper left. `None` when the position is outside of the image.
+    pub fn direction(&self, s: f64, t: f64, aspect: f64) -> Option<Vec3> {
+        match self {
+            Self::Equirectangular => {
+                let phi = (s - 0.5) * 2. * PI;
+                let theta = (0.5 - t) * PI;
+                Some(Vec3::new(
+                    theta.cos() * phi.sin(),
+                    theta.sin(),
+                    theta.cos() * phi.cos(),
+                ))
+            }
+            Self::Fisheye { fov, mapping } => {
+                let x = (2. * s - 1.) * aspect;
+                let y = 1. - 2. * t;
+                let r = (x * x + y * y).sqrt();
+                if r > 1. {
+                    return None;
+                }
+                let half_fov = fov.to_radians() / 2.;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2. * (r * (half_fov / 2.).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+                Some(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ))
+            }
+            Self::Cylindrical {
+                horizontal_fov,
+                vertical_fov,
+            } => {
+                let phi = (s - 0.5) * horizontal_fov.to_radians();
+                let y = (1. - 2. * t) * (vertical_fov.to_radians() / 2.).tan();
+                Some(Vec3::new(phi.sin(), y, phi.cos()))
             }
+            _ => None,
         }
     }
 }
@@ -0,0 +1,71 @@
+use std::ops::Range;
+
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{ray::Ray, vec3::Vec3};
+
+use super::{Hit, Hittable, Shape};
+
+/// A volume of constant density bounded by another shape (smoke, fog, mist).
+/// Pair it with an `Isotropic` material to scatter light evenly inside the volume.
+pub struct ConstantMedium {
+    boundary: Box<Shape>,
+    neg_inv_density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new<S: Into<Shape>>(boundary: S, density: f64) -> Self {
+        Self {
+            boundary: Box::new(boundary.into()),
+            neg_inv_density: -density.recip(),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+        let (t_enter, t_exit) = boundary_span(&self.boundary, rng, ray, hit_range)?;
+
+        let ray_length = ray.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * rng.gen::<f64>().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        Some(medium_hit(ray, t_enter + hit_distance / ray_length))
+    }
+}
+
+/// Range of `t` where the ray is inside of the boundary, limited to the hit range
+pub(crate) fn boundary_span(
+    boundary: &Shape,
+    rng: &mut SmallRng,
+    ray: &Ray,
+    hit_range: Range<f64>,
+) -> Option<(f64, f64)> {
+    let enter = boundary.hit(rng, ray, f64::NEG_INFINITY..f64::INFINITY)?;
+    let exit = boundary.hit(rng, ray, (enter.t + 0.0001)..f64::INFINITY)?;
+
+    let t_enter = enter.t.max(hit_range.start).max(0.);
+    let t_exit = exit.t.min(hit_range.end);
+    if t_enter >= t_exit {
+        return None;
+    }
+    Some((t_enter, t_exit))
+}
+
+/// Scattering event inside of a volume
+pub(crate) fn medium_hit(ray: &Ray, t: f64) -> Hit {
+    // arbitrary, the phase function does not use it
+    let normal = Vec3::new(1., 0., 0.);
+    Hit {
+        t,
+        point: ray.at(t),
+        normal,
+        geometric_normal: normal,
+        is_front_face: true,
+        uv: (0., 0.),
+        tangent: Vec3::new(0., 1., 0.),
+    }
+}
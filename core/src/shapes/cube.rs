@@ -4,6 +4,7 @@ use rand::rngs::SmallRng;
 
 use crate::{
     ray::Ray,
+    utils::Interval,
     vec3::{Vec3, ONE},
 };
 
@@ -28,7 +29,7 @@ impl Cube {
 }
 
 impl Hittable for Cube {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, _rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
         let direction = ray.direction();
         let cmin = (self.vmin - ray.origin()) / direction;
         let cmax = (self.vmax - ray.origin()) / direction;
@@ -38,33 +39,41 @@ impl Hittable for Cube {
         if tmax < 0.0 || tmin > tmax {
             return None;
         }
-        let point = ray.at(tmin);
+        let t = if hit_range.surrounds(&tmin) {
+            tmin
+        } else if hit_range.surrounds(&tmax) {
+            tmax
+        } else {
+            return None;
+        };
+        let point = ray.at(t);
+
+        // position on the cube scaled to -1 to 1, the largest axis is the face that was hit
         let center = (self.vmin + self.vmax) / 2.0;
-        // let rel_c_center = ray.origin() + direction * tmin - (self.vmin + self.vmax) / 2.0;
-        //
-        // let s = rel_c_center.signum();
-        // let a = rel_c_center.abs();
-        //
-        // let normal: Vec3 = if a.z > a.y {
-        //     if a.z > a.x {
-        //         (0., 0., s.z)
-        //     } else {
-        //         (s.x, 0., 0.)
-        //     }
-        // } else {
-        //     if a.y > a.x {
-        //         (0., s.y, 0.)
-        //     } else {
-        //         (s.x, 0., 0.)
-        //     }
-        // }
-        // .into();
+        let rel = (point - center) / ((self.vmax - self.vmin) / 2.0);
+        let a = rel.abs();
+        let s = rel.signum();
+
+        let (normal, tangent, uv) = if a.x >= a.y && a.x >= a.z {
+            (
+                Vec3::new(s.x, 0., 0.),
+                Vec3::new(0., 0., -s.x),
+                ((1. - s.x * rel.z) / 2., (rel.y + 1.) / 2.),
+            )
+        } else if a.y >= a.z {
+            (
+                Vec3::new(0., s.y, 0.),
+                Vec3::new(1., 0., 0.),
+                ((rel.x + 1.) / 2., (1. - s.y * rel.z) / 2.),
+            )
+        } else {
+            (
+                Vec3::new(0., 0., s.z),
+                Vec3::new(s.z, 0., 0.),
+                ((s.z * rel.x + 1.) / 2., (rel.y + 1.) / 2.),
+            )
+        };
 
-        Some(Hit::new(
-            ray,
-            tmin,
-            point,
-            (point - Vec3::new(0.0, 0.0, -1.0)).normalize(),
-        ))
+        Some(Hit::new(ray, t, point, normal).with_surface(uv, tangent))
     }
 }
@@ -0,0 +1,200 @@
+use std::{fs, io, ops::Range, path::Path};
+
+use rand::{rngs::SmallRng, Rng};
+
+use crate::{noise::Perlin, ray::Ray, vec3::Vec3};
+
+use super::{
+    constant_medium::{boundary_span, medium_hit},
+    Hit, Hittable, Shape,
+};
+
+/// Delta tracking steps taken along a ray before it is treated as passing through
+const MAX_STEPS: u32 = 1 << 16;
+
+/// A volume whose density varies in space (clouds, explosions).
+/// Scattering events are found with delta tracking against the maximum density of the field.
+pub struct HeterogeneousMedium {
+    boundary: Box<Shape>,
+    density: Density,
+    max_density: f64,
+}
+
+impl HeterogeneousMedium {
+    pub fn new<S: Into<Shape>>(boundary: S, density: Density) -> Self {
+        let max_density = density.max();
+        Self {
+            boundary: Box::new(boundary.into()),
+            density,
+            max_density,
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+        let (t_enter, t_exit) = boundary_span(&self.boundary, rng, ray, hit_range)?;
+        if self.max_density <= 0. {
+            return None;
+        }
+
+        let ray_length = ray.direction().length();
+        let mut t = t_enter;
+        for _ in 0..MAX_STEPS {
+            let next = t - rng.gen::<f64>().ln() / (self.max_density * ray_length);
+            // the step is below the precision of t, it would never reach the exit
+            if next >= t_exit || next <= t {
+                return None;
+            }
+            t = next;
+            if rng.gen::<f64>() * self.max_density < self.density.at(ray.at(t)) {
+                return Some(medium_hit(ray, t));
+            }
+        }
+        None
+    }
+}
+
+/// Density field of a heterogeneous medium
+pub enum Density {
+    /// Procedural turbulence noise scaled into `0..density`
+    Noise {
+        perlin: Perlin,
+        density: f64,
+        frequency: f64,
+        octaves: u32,
+    },
+    Grid(VoxelGrid),
+}
+
+impl Density {
+    pub fn noise<R: Rng>(rng: &mut R, density: f64, frequency: f64, octaves: u32) -> Self {
+        Self::Noise {
+            perlin: Perlin::new(rng),
+            density,
+            frequency,
+            octaves,
+        }
+    }
+
+    pub fn at(&self, p: Vec3) -> f64 {
+        match self {
+            Density::Noise {
+                perlin,
+                density,
+                frequency,
+                octaves,
+            } => density * perlin.turbulence(*frequency * p, *octaves).min(1.),
+            Density::Grid(grid) => grid.at(p),
+        }
+    }
+
+    /// Upper bound of the density anywhere in the field
+    pub fn max(&self) -> f64 {
+        match self {
+            Density::Noise { density, .. } => *density,
+            Density::Grid(grid) => grid.max(),
+        }
+    }
+}
+
+/// Densities sampled on a regular grid spanning `min` to `max`, trilinearly interpolated
+pub struct VoxelGrid {
+    min: Vec3,
+    max: Vec3,
+    dims: [usize; 3],
+    data: Vec<f64>,
+}
+
+impl VoxelGrid {
+    pub fn new<A: Into<Vec3>, B: Into<Vec3>>(
+        min: A,
+        max: B,
+        dims: [usize; 3],
+        data: Vec<f64>,
+    ) -> io::Result<Self> {
+        if dims.contains(&0) || dims.iter().product::<usize>() != data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "voxel grid {:?} does not match {} densities",
+                    dims,
+                    data.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            min: min.into(),
+            max: max.into(),
+            dims,
+            data,
+        })
+    }
+
+    /// Loads a grid from a text file.
+    /// The first three numbers are the x, y and z resolution followed by the densities with x varying fastest.
+    pub fn load<P: AsRef<Path>, A: Into<Vec3>, B: Into<Vec3>>(
+        path: P,
+        min: A,
+        max: B,
+    ) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut values = contents.split_whitespace();
+
+        let mut dims = [0; 3];
+        for d in dims.iter_mut() {
+            *d = values
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "missing dimension"))?
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        let data = values
+            .map(|v| {
+                v.parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect::<io::Result<Vec<f64>>>()?;
+
+        Self::new(min, max, dims, data)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.data.iter().cloned().fold(0., f64::max)
+    }
+
+    pub fn at(&self, p: Vec3) -> f64 {
+        let rel = (p - self.min) / (self.max - self.min);
+        if !(0.0..=1.0).contains(&rel.x)
+            || !(0.0..=1.0).contains(&rel.y)
+            || !(0.0..=1.0).contains(&rel.z)
+        {
+            return 0.;
+        }
+
+        // continuous voxel coordinates
+        let [nx, ny, nz] = self.dims;
+        let gx = rel.x * (nx - 1) as f64;
+        let gy = rel.y * (ny - 1) as f64;
+        let gz = rel.z * (nz - 1) as f64;
+        let (x0, y0, z0) = (
+            gx.floor() as usize,
+            gy.floor() as usize,
+            gz.floor() as usize,
+        );
+        let (fx, fy, fz) = (gx - x0 as f64, gy - y0 as f64, gz - z0 as f64);
+
+        let mut accum = 0.;
+        for (dz, wz) in [(0, 1. - fz), (1, fz)] {
+            for (dy, wy) in [(0, 1. - fy), (1, fy)] {
+                for (dx, wx) in [(0, 1. - fx), (1, fx)] {
+                    let x = (x0 + dx).min(nx - 1);
+                    let y = (y0 + dy).min(ny - 1);
+                    let z = (z0 + dz).min(nz - 1);
+                    accum += wx * wy * wz * self.data[x + nx * (y + ny * z)];
+                }
+            }
+        }
+        accum
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(unused)]
 pub mod aabb;
+pub mod constant_medium;
 pub mod cube;
+pub mod heterogeneous_medium;
 pub mod sphere;
 
 use std::ops::Range;
@@ -9,26 +11,29 @@ use rand::rngs::SmallRng;
 
 use crate::{color::Color, ray::Ray, vec3::Vec3};
 
-use self::{cube::Cube, sphere::Sphere};
+use self::{
+    constant_medium::ConstantMedium, cube::Cube, heterogeneous_medium::HeterogeneousMedium,
+    sphere::Sphere,
+};
 
 /// Describes a shape that is hittable
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit>;
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit>;
 }
 
 impl<H: Hittable + ?Sized> Hittable for Box<H> {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
-        (**self).hit(ray, hit_range)
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+        (**self).hit(rng, ray, hit_range)
     }
 }
 
 impl<H: Hittable> Hittable for &[H] {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
         let mut hit = None;
         let mut cur_range = hit_range;
 
         for obj in self.iter() {
-            if let Some(h) = obj.hit(ray, cur_range.clone()) {
+            if let Some(h) = obj.hit(rng, ray, cur_range.clone()) {
                 cur_range.end = h.t;
                 hit = Some(h);
             }
@@ -38,21 +43,52 @@ impl<H: Hittable> Hittable for &[H] {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Hit {
     pub t: f64,
     pub point: Vec3,
+    /// Normal used for shading, may be perturbed by normal or bump maps
     pub normal: Vec3,
+    /// Normal of the actual surface, always facing against the ray
+    pub geometric_normal: Vec3,
     pub is_front_face: bool,
+    /// Surface coordinates in the range of 0 to 1
+    pub uv: (f64, f64),
+    /// Surface direction of increasing `u`, perpendicular to the normal
+    pub tangent: Vec3,
 }
 
 impl Hit {
     pub fn new(ray: &Ray, t: f64, point: Vec3, normal: Vec3) -> Self {
         let is_front_face = ray.direction().dot(normal) < 0.;
+        let normal = if is_front_face { normal } else { -normal };
+        let (tangent, _) = normal.orthonormal_basis();
         Self {
             t,
             point,
             is_front_face,
-            normal: if is_front_face { normal } else { -normal },
+            normal,
+            geometric_normal: normal,
+            uv: (0., 0.),
+            tangent,
+        }
+    }
+
+    /// Sets the surface coordinates and tangent frame
+    pub fn with_surface(mut self, uv: (f64, f64), tangent: Vec3) -> Self {
+        self.uv = uv;
+        // keep the tangent perpendicular to the normal
+        self.tangent = (tangent - tangent.dot(self.normal) * self.normal).normalize();
+        self
+    }
+
+    /// Direction of increasing `v`
+    pub fn bitangent(&self) -> Vec3 {
+        // the normal is flipped on back faces, the surface directions are not
+        if self.is_front_face {
+            self.normal.cross(self.tangent)
+        } else {
+            self.tangent.cross(self.normal)
         }
     }
 }
@@ -60,6 +96,8 @@ impl Hit {
 pub enum Shape {
     Sphere(Sphere),
     Cube(Cube),
+    ConstantMedium(ConstantMedium),
+    HeterogeneousMedium(HeterogeneousMedium),
     Custom(Box<dyn Hittable + Send + Sync>),
 }
 
@@ -75,6 +113,8 @@ macro_rules! from_shape {
 
 from_shape!(Sphere);
 from_shape!(Cube);
+from_shape!(ConstantMedium);
+from_shape!(HeterogeneousMedium);
 
 impl From<Box<dyn Hittable + Send + Sync>> for Shape {
     fn from(value: Box<dyn Hittable + Send + Sync>) -> Self {
@@ -82,12 +122,24 @@ impl From<Box<dyn Hittable + Send + Sync>> for Shape {
     }
 }
 
+impl Shape {
+    /// Distance moved over the shutter interval
+    pub fn velocity(&self) -> Vec3 {
+        match self {
+            Shape::Sphere(s) => s.velocity(),
+            _ => Vec3::new(0., 0., 0.),
+        }
+    }
+}
+
 impl Hittable for Shape {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
         match self {
-            Shape::Sphere(h) => h.hit(ray, hit_range),
-            Shape::Cube(h) => h.hit(ray, hit_range),
-            Shape::Custom(h) => h.hit(ray, hit_range),
+            Shape::Sphere(h) => h.hit(rng, ray, hit_range),
+            Shape::Cube(h) => h.hit(rng, ray, hit_range),
+            Shape::ConstantMedium(h) => h.hit(rng, ray, hit_range),
+            Shape::HeterogeneousMedium(h) => h.hit(rng, ray, hit_range),
+            Shape::Custom(h) => h.hit(rng, ray, hit_range),
         }
     }
 }
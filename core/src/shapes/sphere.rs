@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::{f64::consts::PI, ops::Range};
 
 use rand::rngs::SmallRng;
 
@@ -30,10 +30,15 @@ impl Sphere {
             radius,
         }
     }
+
+    /// Distance the center moves over the shutter interval
+    pub fn velocity(&self) -> Vec3 {
+        self.center_vec.unwrap_or(Vec3::new(0., 0., 0.))
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
+    fn hit(&self, _rng: &mut SmallRng, ray: &Ray, hit_range: Range<f64>) -> Option<Hit> {
         let center = self.center;
         let center = self
             .center_vec
@@ -63,6 +68,17 @@ impl Hittable for Sphere {
 
         let point = ray.at(root);
         let normal = (point - center) / self.radius;
-        Some(Hit::new(ray, root, point, normal))
+
+        let theta = (-normal.y).acos();
+        let phi = (-normal.z).atan2(normal.x) + PI;
+        let uv = (phi / (2. * PI), theta / PI);
+        // derivative of the point along `u`
+        let tangent = if normal.x == 0. && normal.z == 0. {
+            Vec3::new(1., 0., 0.)
+        } else {
+            Vec3::new(normal.z, 0., -normal.x)
+        };
+
+        Some(Hit::new(ray, root, point, normal).with_surface(uv, tangent))
     }
 }
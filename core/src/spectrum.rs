@@ -0,0 +1,110 @@
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+use crate::color::Color;
+
+/// Shortest visible wavelength sampled in nanometers
+pub const LAMBDA_MIN: f64 = 380.;
+/// Longest visible wavelength sampled in nanometers
+pub const LAMBDA_MAX: f64 = 720.;
+
+/// Uniformly picks a visible wavelength in nanometers
+pub fn sample_wavelength<R: Rng>(rng: &mut R) -> f64 {
+    rng.gen_range(LAMBDA_MIN..LAMBDA_MAX)
+}
+
+/// Upsamples an RGB reflectance into a smooth spectrum evaluated at the wavelength.
+/// The blue, green and red basis functions sum to one so white stays constant across the spectrum.
+pub fn reflectance(color: Color, wavelength: f64) -> f64 {
+    let [r, g, b] = color.into_arr();
+    let blue = 1. - smoothstep(480., 510., wavelength);
+    let red = smoothstep(570., 610., wavelength);
+    let green = 1. - blue - red;
+    r * red + g * green + b * blue
+}
+
+/// Linear sRGB contribution of a single uniformly sampled wavelength with unit radiance.
+/// Averaging this over many samples of a constant spectrum of one gives white.
+pub fn to_rgb(wavelength: f64) -> Color {
+    static WHITE_BALANCE: OnceLock<[f64; 3]> = OnceLock::new();
+    let white = WHITE_BALANCE.get_or_init(|| {
+        const STEPS: usize = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as f64;
+        (0..STEPS)
+            .map(|i| xyz_to_srgb(cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step)))
+            .fold([0.; 3], |acc, c| {
+                [
+                    acc[0] + c[0] * step,
+                    acc[1] + c[1] * step,
+                    acc[2] + c[2] * step,
+                ]
+            })
+    });
+
+    let rgb = xyz_to_srgb(cie_xyz(wavelength));
+    let scale = LAMBDA_MAX - LAMBDA_MIN;
+    Color::new(
+        rgb[0] * scale / white[0],
+        rgb[1] * scale / white[1],
+        rgb[2] * scale / white[2],
+    )
+}
+
+/// Linear sRGB color of a black body radiator at the temperature in kelvin, scaled to a peak of 1
+pub fn blackbody_rgb(temperature: f64) -> Color {
+    const STEPS: usize = 100;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as f64;
+    let xyz = (0..STEPS)
+        .map(|i| {
+            let wavelength = LAMBDA_MIN + (i as f64 + 0.5) * step;
+            let radiance = planck(wavelength, temperature);
+            cie_xyz(wavelength).map(|c| c * radiance)
+        })
+        .fold([0.; 3], |acc, c| {
+            [acc[0] + c[0], acc[1] + c[1], acc[2] + c[2]]
+        });
+    let rgb = xyz_to_srgb(xyz).map(|c| c.max(0.));
+    let peak = rgb.iter().cloned().fold(f64::EPSILON, f64::max);
+    Color::new(rgb[0] / peak, rgb[1] / peak, rgb[2] / peak)
+}
+
+/// Spectral radiance of a black body, without constant factors
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+    // second radiation constant in nanometer kelvin
+    const C2: f64 = 1.4388e7;
+    let l = wavelength / 1000.;
+    1. / (l.powi(5) * ((C2 / (wavelength * temperature)).exp() - 1.))
+}
+
+/// CIE 1931 color matching functions using the multi-lobe fit by Wyman, Sloan and Shirley
+pub fn cie_xyz(wavelength: f64) -> [f64; 3] {
+    let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+    [x, y, z]
+}
+
+/// CIE XYZ to linear sRGB (D65)
+pub fn xyz_to_srgb([x, y, z]: [f64; 3]) -> [f64; 3] {
+    [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ]
+}
+
+fn lobe(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
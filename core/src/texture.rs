@@ -0,0 +1,104 @@
+use std::io;
+
+use crate::{noise::Perlin, vec3::Vec3};
+
+/// Scalar value that varies over a surface
+pub enum Texture {
+    Constant(f64),
+    /// Alternating squares in uv space, `scale` squares per unit
+    Checker {
+        scale: f64,
+        even: f64,
+        odd: f64,
+    },
+    /// Procedural turbulence in the range of 0 to 1
+    Noise {
+        perlin: Perlin,
+        frequency: f64,
+    },
+    Image(ImageTexture),
+}
+
+impl Texture {
+    pub fn value(&self, uv: (f64, f64), point: Vec3) -> f64 {
+        match self {
+            Texture::Constant(v) => *v,
+            Texture::Checker { scale, even, odd } => {
+                let cell = (uv.0 * scale).floor() as i64 + (uv.1 * scale).floor() as i64;
+                if cell % 2 == 0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::Noise { perlin, frequency } => {
+                perlin.turbulence(*frequency * point, 7).min(1.)
+            }
+            Texture::Image(img) => img.at(uv),
+        }
+    }
+}
+
+impl From<f64> for Texture {
+    fn from(value: f64) -> Self {
+        Self::Constant(value)
+    }
+}
+
+/// Grayscale image sampled with nearest neighbour lookup
+#[derive(Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl ImageTexture {
+    /// Values are listed row by row starting at the top left of the image
+    pub fn new(width: usize, height: usize, data: Vec<f64>) -> io::Result<Self> {
+        if width == 0 || height == 0 || width * height != data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "image texture of {}x{} does not match {} values",
+                    width,
+                    height,
+                    data.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Loads the luminance of an image, or the alpha channel when `alpha` is set
+    #[cfg(feature = "image")]
+    pub fn open<P: AsRef<std::path::Path>>(path: P, alpha: bool) -> image::ImageResult<Self> {
+        let img = image::open(path)?.into_rgba32f();
+        let data = img
+            .pixels()
+            .map(|p| {
+                if alpha {
+                    p[3] as f64
+                } else {
+                    0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64
+                }
+            })
+            .collect();
+        Ok(Self::new(
+            img.width() as usize,
+            img.height() as usize,
+            data,
+        )?)
+    }
+
+    pub fn at(&self, (u, v): (f64, f64)) -> f64 {
+        let x = ((u.clamp(0., 1.) * self.width as f64) as usize).min(self.width - 1);
+        // images start at the top while v starts at the bottom
+        let y = (((1. - v.clamp(0., 1.)) * self.height as f64) as usize).min(self.height - 1);
+        self.data[x + y * self.width]
+    }
+}
@@ -55,6 +55,30 @@ impl Vec3 {
     pub fn normalize(self) -> Self {
         self / self.length()
     }
+
+    /// Two unit vectors perpendicular to this normalized vector and each other
+    pub fn orthonormal_basis(self) -> (Self, Self) {
+        let a = if self.x.abs() > 0.9 {
+            Self::new(0., 1., 0.)
+        } else {
+            Self::new(1., 0., 0.)
+        };
+        let v = self.cross(a).normalize();
+        let u = v.cross(self);
+        (u, v)
+    }
+
+    /// Transforms a vector in the local frame where this normalized vector is `z` to world space
+    pub fn local_to_world(self, local: Self) -> Self {
+        let (u, v) = self.orthonormal_basis();
+        local.x * u + local.y * v + local.z * self
+    }
+
+    /// Inverse of `local_to_world`
+    pub fn world_to_local(self, world: Self) -> Self {
+        let (u, v) = self.orthonormal_basis();
+        Self::new(world.dot(u), world.dot(v), world.dot(self))
+    }
 }
 
 impl Neg for Vec3 {
@@ -114,6 +138,7 @@ impl Mul<Vec3> for f64 {
 impl Div<f64> for Vec3 {
     type Output = Self;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: f64) -> Self::Output {
         rhs.recip() * self
     }
@@ -1,30 +1,65 @@
 use std::ops::Range;
 
-use rand::rngs::SmallRng;
+use rand::{rngs::SmallRng, Rng};
 
 use crate::{
-    color::Color,
+    color::{Color, SKY_BLUE, WHITE},
     materials::{Material, Scatter},
     ray::Ray,
-    shapes::{Hittable, Shape},
+    shapes::{Hit, Hittable, Shape},
+    texture::Texture,
+    vec3::Vec3,
 };
 
-pub struct World(Vec<Object>);
+pub struct World {
+    objects: Vec<Object>,
+    fog: Option<Fog>,
+    background: Background,
+}
 
 impl From<Vec<Object>> for World {
     fn from(value: Vec<Object>) -> Self {
-        Self(value)
+        Self {
+            objects: value,
+            fog: None,
+            background: Background::default(),
+        }
     }
 }
 
 impl World {
+    /// Fills the whole scene with a constant density atmosphere
+    pub fn with_fog(mut self, fog: Fog) -> Self {
+        self.fog = Some(fog);
+        self
+    }
+
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
     pub fn cast(&self, rng: &mut SmallRng, ray: &Ray, cast_range: Range<f64>) -> Option<Cast> {
         let mut cast = None;
         let mut cur_range = cast_range;
 
-        for obj in self.0.iter() {
-            if let Some(c) = obj.cast(rng, ray, cur_range.clone()) {
+        if let Some(fog) = &self.fog {
+            if let Some(c) = fog.cast(rng, ray, cur_range.clone()) {
+                cur_range.end = c.t;
+                cast = Some(c);
+            }
+        }
+
+        for (id, obj) in self.objects.iter().enumerate() {
+            if let Some(mut c) = obj.cast(rng, ray, cur_range.clone()) {
                 cur_range.end = c.t;
+                if let Some(surface) = &mut c.surface {
+                    surface.object_id = id;
+                }
                 cast = Some(c);
             }
         }
@@ -37,30 +72,196 @@ pub struct Cast {
     t: f64,
     pub bounce: Ray,
     pub color: Option<Color>,
+    /// Surface that was hit, `None` when scattered by the fog
+    pub surface: Option<Surface>,
+}
+
+/// Details of the surface at a cast used for render passes
+#[derive(Debug, Clone)]
+pub struct Surface {
+    pub normal: Vec3,
+    /// Index of the object in the world
+    pub object_id: usize,
+    pub material_id: u32,
+    /// Movement of the surface over the whole shutter interval
+    pub velocity: Vec3,
 }
 
 pub struct Object {
     pub shape: Shape,
     pub mat: Material,
+    /// Cutout mask, parts of the surface that are not opaque are skipped
+    pub opacity: Option<Opacity>,
+    /// How the inside of the surface is shaded
+    pub back_face: BackFace,
+    /// ID written to the material ID render pass, defaults to the kind of material
+    pub material_id: u32,
 }
 
 impl Object {
     pub fn new<H: Into<Shape>, M: Into<Material>>(shape: H, mat: M) -> Object {
+        let mat = mat.into();
         Self {
             shape: shape.into(),
-            mat: mat.into(),
+            material_id: mat.kind_id(),
+            mat,
+            opacity: None,
+            back_face: BackFace::Shade,
         }
     }
 
+    pub fn with_opacity<T: Into<Texture>>(mut self, texture: T, mode: AlphaMode) -> Self {
+        self.opacity = Some(Opacity {
+            texture: texture.into(),
+            mode,
+        });
+        self
+    }
+
+    pub fn with_back_face(mut self, back_face: BackFace) -> Self {
+        self.back_face = back_face;
+        self
+    }
+
+    pub fn with_material_id(mut self, material_id: u32) -> Self {
+        self.material_id = material_id;
+        self
+    }
+
     pub fn cast(&self, rng: &mut SmallRng, ray: &Ray, cast_range: Range<f64>) -> Option<Cast> {
-        if let Some(hit) = self.shape.hit(ray, cast_range) {
-            let (bounce, color) = self.mat.scatter(rng, ray, &hit);
-            return Some(Cast {
-                t: hit.t,
-                bounce,
-                color,
-            });
+        let mut cur_range = cast_range;
+        loop {
+            let hit = self.shape.hit(rng, ray, cur_range.clone())?;
+
+            let mat = match &self.back_face {
+                _ if hit.is_front_face => Some(&self.mat),
+                BackFace::Shade => Some(&self.mat),
+                BackFace::Invisible => None,
+                BackFace::Material(m) => Some(m),
+            };
+            let opaque = self
+                .opacity
+                .as_ref()
+                .map(|o| o.is_opaque(rng, &hit))
+                .unwrap_or(true);
+
+            match mat {
+                Some(mat) if opaque => {
+                    let (bounce, color) = mat.scatter(rng, ray, &hit);
+                    return Some(Cast {
+                        t: hit.t,
+                        bounce,
+                        color,
+                        surface: Some(Surface {
+                            normal: hit.normal,
+                            object_id: 0,
+                            material_id: self.material_id,
+                            velocity: self.shape.velocity(),
+                        }),
+                    });
+                }
+                // look for the next surface behind this one, always moving forward so shapes
+                // that report a hit at the start of the range can't stall the loop
+                _ => cur_range.start = hit.t.max(cur_range.start) + 0.0001,
+            }
+        }
+    }
+}
+
+/// Opacity texture of an object where 0 is fully transparent and 1 is opaque
+pub struct Opacity {
+    pub texture: Texture,
+    pub mode: AlphaMode,
+}
+
+impl Opacity {
+    fn is_opaque(&self, rng: &mut SmallRng, hit: &Hit) -> bool {
+        let alpha = self.texture.value(hit.uv, hit.point);
+        match self.mode {
+            AlphaMode::Stochastic => rng.gen::<f64>() < alpha,
+            AlphaMode::Threshold(cutoff) => alpha >= cutoff,
         }
-        None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AlphaMode {
+    /// Randomly lets rays through in proportion to the transparency, giving soft edges
+    Stochastic,
+    /// Surface is solid where the opacity is at least the cutoff, otherwise it is a hole
+    Threshold(f64),
+}
+
+pub enum BackFace {
+    /// Shade with the same material as the front face
+    Shade,
+    /// Rays pass through the back of the surface
+    Invisible,
+    /// Shade with a separate material
+    Material(Material),
+}
+
+/// Light of rays leaving the scene
+#[derive(Debug, Clone, Default)]
+pub enum Background {
+    /// White at the horizon blending to sky blue straight up
+    #[default]
+    Sky,
+    Solid(Color),
+    /// Blends from `bottom` straight down to `top` straight up
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+}
+
+impl Background {
+    pub fn color(&self, direction: Vec3) -> Color {
+        match self {
+            Background::Sky => {
+                let gradiant = 0.5 * (direction.y + 1.0);
+                (1.0 - gradiant) * WHITE + gradiant * SKY_BLUE
+            }
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let gradiant = 0.5 * (direction.normalize().y + 1.0);
+                bottom.lerp(*top, gradiant)
+            }
+        }
+    }
+}
+
+/// Global atmosphere that scatters rays isotropically at a constant density everywhere
+#[derive(Debug, Clone)]
+pub struct Fog {
+    pub density: f64,
+    pub albedo: Color,
+}
+
+impl Fog {
+    pub fn new(density: f64, albedo: Color) -> Self {
+        Self { density, albedo }
+    }
+
+    fn cast(&self, rng: &mut SmallRng, ray: &Ray, cast_range: Range<f64>) -> Option<Cast> {
+        if self.density <= 0. {
+            return None;
+        }
+        let ray_length = ray.direction().length();
+        let hit_distance = -self.density.recip() * rng.gen::<f64>().ln();
+        let t = cast_range.start + hit_distance / ray_length;
+        if t >= cast_range.end {
+            return None;
+        }
+
+        let bounce = ray
+            .at(t)
+            .ray_timed(Vec3::random_unit_sphere(rng), ray.time());
+        Some(Cast {
+            t,
+            bounce,
+            color: Some(self.albedo),
+            surface: None,
+        })
     }
 }
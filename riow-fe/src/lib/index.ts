@@ -57,18 +57,35 @@ export type ImageConfig = {
 	height: number;
 };
 
-const ImageStatusSchema = z.object({
-	Rendering: z
-		.object({
-			cur_pixel: z.number().int(),
-			max_pixels: z.number().int()
-		})
-		.optional(),
-	download_url: z.string().url().optional()
-});
+export const ImageStatusSchema = z.preprocess(
+	// a cancelled job is the plain string "Cancelled"
+	(status) => (status === 'Cancelled' ? { Cancelled: true } : status),
+	z.object({
+		Queued: z
+			.object({
+				position: z.number().int(),
+				estimated_start: z.string().nullable()
+			})
+			.optional(),
+		Rendering: z
+			.object({
+				cur_pixel: z.number().int(),
+				max_pixels: z.number().int()
+			})
+			.optional(),
+		Cancelled: z.boolean().optional(),
+		download_url: z.string().url().optional()
+	})
+);
 
 export type ImageStatus = z.infer<typeof ImageStatusSchema>;
 
+export const PreviewFrameSchema = z.object({
+	pass: z.number().int(),
+	passes: z.number().int(),
+	image: z.string()
+});
+
 export async function imageGenStatus(url: string): Promise<ImageStatus> {
 	const res = await fetch(url);
 	return ImageStatusSchema.parse(await res.json());
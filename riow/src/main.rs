@@ -7,7 +7,11 @@ use raytracing_iow::{
     materials::{dielectric::Dielectric, lambertian::Lambertain, metal::Metal},
     render::{
         camera::{Camera, CameraConfig},
+        denoise::Denoiser,
+        filter::Filter,
+        post::{ColorGrade, Effect, Lut},
         screen::Screen,
+        stereo::{Stereo, StereoLayout},
         viewport::ViewportConfig,
     },
     shapes::sphere::Sphere,
@@ -15,17 +19,85 @@ use raytracing_iow::{
     world::{Object, World},
 };
 
-use crate::renderer::Renderer;
+use crate::renderer::{AovOutput, Renderer};
 
 const ASPECT_RATIO: f64 = 16. / 9.;
 const ORANGE: Color = Color::new(250. / 256., 121. / 256., 35. / 256.);
 const PURPLE: Color = Color::new(71. / 256., 5. / 256., 158. / 256.);
 
+/// Distance between the eyes in scene units for stereo renders
+const IPD: f64 = 0.2;
+
 fn main() -> io::Result<()> {
-    let mut filename = args().nth(1).unwrap_or("rendering".to_owned());
-    filename.push_str(".ppm");
+    let (flags, names): (Vec<String>, Vec<String>) =
+        args().skip(1).partition(|arg| arg.starts_with("--"));
+    let name = names.into_iter().next().unwrap_or("rendering".to_owned());
+    let filename = format!("{name}.ppm");
+
+    // --aovs writes every render pass into one multi-layer EXR, --aovs=separate to one file each
+    let aov_output = flags.iter().find_map(|flag| match flag.as_str() {
+        "--aovs" => Some(AovOutput::MultiLayer(format!("{name}.exr").into())),
+        "--aovs=separate" => Some(AovOutput::Separate(name.clone())),
+        _ => None,
+    });
+    // --spectral traces a single wavelength per sample instead of RGB
+    let spectral = flags.iter().any(|flag| flag == "--spectral");
+    // --denoise or --denoise=<strength from 0 to 1>
+    let denoiser = flags
+        .iter()
+        .find_map(|flag| match flag.as_str() {
+            "--denoise" => Some(Ok(1.)),
+            flag => flag.strip_prefix("--denoise=").map(str::parse::<f64>),
+        })
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .map(Denoiser::new);
+    // --post=bloom,vignette,aberration,grain and --lut=<file.cube> for color grading
+    let mut effects = flags
+        .iter()
+        .filter_map(|flag| flag.strip_prefix("--post="))
+        .flat_map(|list| list.split(','))
+        .map(parse_effect)
+        .collect::<io::Result<Vec<Effect>>>()?;
+    if let Some(path) = flags.iter().find_map(|flag| flag.strip_prefix("--lut=")) {
+        effects.push(Effect::ColorGrade(ColorGrade {
+            lut: Some(Lut::load(path)?),
+            ..Default::default()
+        }));
+    }
+
+    // --stereo=side-by-side, --stereo=top-bottom or --stereo=ods for a 360 VR panorama
+    let stereo_mode = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--stereo="))
+        .map(str::to_owned);
+    let layout = match stereo_mode.as_deref() {
+        None => None,
+        Some("side-by-side") => Some(StereoLayout::SideBySide),
+        Some("top-bottom") | Some("ods") => Some(StereoLayout::TopBottom),
+        Some(other) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown stereo mode {other}"),
+            ))
+        }
+    };
+    let ods = stereo_mode.as_deref() == Some("ods");
+    // --convergence=<distance> where both eyes line up, defaults to the focus distance
+    let convergence = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--convergence="))
+        .map(str::parse::<f64>)
+        .transpose()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
-    let screen = Screen::new_aspect_ratio(1200, ASPECT_RATIO);
+    let screen = match layout {
+        // two 2:1 panoramas stacked on top of each other
+        _ if ods => Screen::new(1200, 1200),
+        Some(StereoLayout::SideBySide) => Screen::new_aspect_ratio(2400, ASPECT_RATIO * 2.),
+        Some(StereoLayout::TopBottom) => Screen::new_aspect_ratio(1200, ASPECT_RATIO / 2.),
+        None => Screen::new_aspect_ratio(1200, ASPECT_RATIO),
+    };
     let camera_config = CameraConfig {
         samples_per_pixel: 500,
         max_depth: 50,
@@ -34,10 +106,33 @@ fn main() -> io::Result<()> {
         up: (0., 1., 0.).into(),
         defocus_angle: 0.6,
         focus_dist: 10.0,
+        spectral,
+        lens: None,
+        filter: Filter::default(),
+        seed: None,
+    };
+    let viewport_config = if ods {
+        ViewportConfig::Equirectangular
+    } else {
+        ViewportConfig::Fov { vertical_fov: 20.0 }
     };
-    let viewport_config = ViewportConfig::Fov { vertical_fov: 20.0 };
+    let stereo = layout
+        .map(|layout| {
+            let convergence = convergence.unwrap_or(camera_config.focus_dist);
+            Stereo::new(IPD, convergence, layout)
+        })
+        .transpose()?;
 
-    let camera = Camera::new(camera_config, viewport_config);
+    let mut camera = Camera::new(camera_config, viewport_config);
+    if aov_output.is_some() || denoiser.is_some() {
+        if stereo.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Render passes and denoising are not supported for stereo renders",
+            ));
+        }
+        camera = camera.with_aovs();
+    }
 
     let mut world = vec![
         Object::new(
@@ -70,7 +165,31 @@ fn main() -> io::Result<()> {
     println!("Setup World Starting Render");
 
     let mut renderer = Renderer::new(screen, &filename)?;
-    renderer.render(&camera, &World::from(world))?;
+    if let Some(aov_output) = aov_output {
+        renderer = renderer.with_aovs(aov_output);
+    }
+    if let Some(denoiser) = denoiser {
+        renderer = renderer.with_denoiser(denoiser);
+    }
+    renderer = renderer.with_effects(effects);
+    renderer.render(&camera, &World::from(world), stereo.as_ref())?;
 
     Ok(())
 }
+
+fn parse_effect(name: &str) -> io::Result<Effect> {
+    match name {
+        "bloom" => Ok(Effect::Bloom {
+            threshold: 0.8,
+            intensity: 0.3,
+            radius: 0.02,
+        }),
+        "vignette" => Ok(Effect::Vignette { strength: 0.5 }),
+        "aberration" => Ok(Effect::ChromaticAberration { strength: 0.003 }),
+        "grain" => Ok(Effect::Grain { amount: 0.05 }),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown post-processing effect {other}"),
+        )),
+    }
+}
@@ -1,21 +1,41 @@
 use std::{
     fs::File,
     io::{self, BufWriter, Seek, SeekFrom, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use chrono::Utc;
 use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use raytracing_iow::{
     color::Color,
-    render::{camera::Camera, screen::Screen, PixelLocator},
+    render::{
+        aov::Aov,
+        camera::Camera,
+        cancel::CancelToken,
+        denoise::Denoiser,
+        film::Film,
+        post::{post_process, Effect},
+        screen::Screen,
+        stereo::{Eye, Stereo},
+        PixelLocator,
+    },
     world::World,
 };
 
 pub struct Renderer {
     screen: Screen,
     writer: BufWriter<File>,
+    aov_output: Option<AovOutput>,
+    denoiser: Option<Denoiser>,
+    effects: Vec<Effect>,
+}
+
+/// Where the render passes are written
+pub enum AovOutput {
+    /// Beauty and all passes as layers of one EXR file
+    MultiLayer(PathBuf),
+    /// One EXR file per pass named `<name>.<pass>.exr`
+    Separate(String),
 }
 
 impl Renderer {
@@ -23,10 +43,36 @@ impl Renderer {
         Ok(Renderer {
             screen,
             writer: BufWriter::new(File::create(output_file)?),
+            aov_output: None,
+            denoiser: None,
+            effects: Vec::new(),
         })
     }
 
-    pub fn render(&mut self, camera: &Camera, world: &World) -> io::Result<()> {
+    /// Post-processing effects applied in order after denoising
+    pub fn with_effects(mut self, effects: Vec<Effect>) -> Self {
+        self.effects = effects;
+        self
+    }
+
+    /// Denoises the image after rendering, the camera has to collect the render passes
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = Some(denoiser);
+        self
+    }
+
+    /// Writes the render passes after rendering, the camera has to collect them
+    pub fn with_aovs(mut self, output: AovOutput) -> Self {
+        self.aov_output = Some(output);
+        self
+    }
+
+    pub fn render(
+        &mut self,
+        camera: &Camera,
+        world: &World,
+        stereo: Option<&Stereo>,
+    ) -> io::Result<()> {
         let start = Utc::now();
         let width = self.screen.width();
         let height = self.screen.height();
@@ -39,25 +85,56 @@ impl Renderer {
             .unwrap(),
         );
 
-        self.writer.seek(SeekFrom::Start(0))?;
-        write!(self.writer, "P3\n{} {}\n255\n\n", width, height)?;
-
-        let pixel_locator = PixelLocator::from_screen_and_camera(&self.screen, &camera);
-
-        let locator = &pixel_locator;
-        let bar = &progress_bar;
-        let pixels: Vec<Color> = (0..height)
-            .into_par_iter()
-            .flat_map(move |y| {
-                (0..width).into_par_iter().map(move |x| {
-                    let pixel_center = locator.pixel_center(x, y);
+        let (frame, pixels) = match stereo {
+            Some(stereo) => {
+                let eye_screen = stereo.eye_screen(&self.screen);
+                let [left, right] = [Eye::Left, Eye::Right].map(|eye| {
+                    let locator = PixelLocator::for_eye(&eye_screen, camera, stereo, eye);
+                    let film =
+                        Self::render_film(&eye_screen, &locator, camera, world, &progress_bar);
+                    self.finish(film.radiance(), &eye_screen, camera.seed())
+                });
+                let frame = stereo.frame_screen(&eye_screen);
+                (frame, stereo.combine(&eye_screen, left, right))
+            }
+            None => {
+                let locator = PixelLocator::from_screen_and_camera(&self.screen, camera);
+                let film = Self::render_film(&self.screen, &locator, camera, world, &progress_bar);
+                let mut pixels = film.radiance();
+                let aovs = film.aovs();
+                if let (Some(denoiser), Some(aovs)) = (&self.denoiser, &aovs) {
+                    println!("Denoising");
+                    pixels = denoiser.denoise(&pixels, aovs);
+                }
+                if let (Some(output), Some(aovs)) = (&self.aov_output, aovs) {
+                    println!("Writing Render Passes");
+                    let written = match output {
+                        AovOutput::MultiLayer(path) => aovs.write_exr(
+                            BufWriter::new(File::create(path)?),
+                            Some(&pixels),
+                            &Aov::ALL,
+                        ),
+                        AovOutput::Separate(name) => Aov::ALL.iter().try_for_each(|aov| {
+                            let file = File::create(format!("{name}.{}.exr", aov.name()))?;
+                            aovs.write_exr(BufWriter::new(file), None, &[*aov])
+                        }),
+                    };
+                    written.map_err(io::Error::other)?;
+                }
+                (
+                    Screen::new(width, height),
+                    self.finish(pixels, &self.screen, camera.seed()),
+                )
+            }
+        };
 
-                    let pixel = camera.get_color(world, locator, pixel_center);
-                    bar.inc(1);
-                    pixel
-                })
-            })
-            .collect();
+        self.writer.seek(SeekFrom::Start(0))?;
+        write!(
+            self.writer,
+            "P3\n{} {}\n255\n\n",
+            frame.width(),
+            frame.height()
+        )?;
 
         println!("Writing Pixels");
         for pixel in pixels {
@@ -72,6 +149,29 @@ impl Renderer {
             diff.num_seconds() % 60,
             diff.num_milliseconds() % 1000
         );
+        println!("{}", camera.stats());
         Ok(())
     }
+
+    /// Applies the post-processing effects and clamps the pixels for output
+    fn finish(&self, pixels: Vec<Color>, screen: &Screen, seed: u64) -> Vec<Color> {
+        post_process(&self.effects, pixels, screen, seed)
+            .into_iter()
+            .map(|c| c.clamp(0.0..0.9999))
+            .collect()
+    }
+
+    fn render_film(
+        screen: &Screen,
+        locator: &PixelLocator,
+        camera: &Camera,
+        world: &World,
+        bar: &ProgressBar,
+    ) -> Film {
+        camera
+            .render_film(world, locator, screen, &CancelToken::new(), |pixels| {
+                bar.inc(pixels)
+            })
+            .expect("render is never cancelled")
+    }
 }
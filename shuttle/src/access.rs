@@ -0,0 +1,514 @@
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::AppConfig,
+    state::AppData,
+    store::{Job, JobStore},
+    utils::map_poison_error,
+};
+
+/// Header the API key can be sent in besides `Authorization: Bearer <key>`
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// An API key and its limits as written in the keys file
+#[derive(Deserialize)]
+struct ApiKey {
+    key: String,
+    /// Shown in the logs and owns the jobs submitted with the key
+    name: String,
+    /// Pixels times samples per pixel that can be rendered per UTC day, unlimited when unset.
+    /// Jobs are charged when submitted and refunded when they fail or are cancelled.
+    #[serde(default)]
+    daily_quota: Option<u64>,
+    /// Jobs queued or rendering at the same time, unlimited when unset
+    #[serde(default)]
+    max_concurrent: Option<usize>,
+    /// Overrides `RATE_LIMIT_PER_MINUTE`
+    #[serde(default)]
+    rate_limit_per_minute: Option<f64>,
+    /// Overrides `RATE_LIMIT_BURST`
+    #[serde(default)]
+    rate_limit_burst: Option<f64>,
+    /// Held while a job of the key is checked against the limits and queued
+    #[serde(skip)]
+    admission: Mutex<()>,
+}
+
+/// Who made a request, every request is anonymous when no keys are configured
+#[derive(Clone)]
+pub struct Client(Option<Arc<ApiKey>>);
+
+impl Client {
+    /// Name of the API key, owner of the jobs it submits
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_ref().map(|k| k.name.as_str())
+    }
+
+    /// Whether the client may see and change a job submitted by `owner`
+    pub fn owns(&self, owner: Option<&str>) -> bool {
+        self.0.is_none() || self.name() == owner
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppData> for Client {
+    type Rejection = AccessError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppData,
+    ) -> Result<Self, Self::Rejection> {
+        state.access.authenticate(&parts.headers)
+    }
+}
+
+/// Client of a route that browsers open with `EventSource`, which can't send headers, so the key
+/// can also be given as the `api_key` query parameter
+pub struct StreamClient(pub Client);
+
+#[derive(Deserialize)]
+struct KeyQuery {
+    api_key: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<AppData> for StreamClient {
+    type Rejection = AccessError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppData,
+    ) -> Result<Self, Self::Rejection> {
+        let query = Query::<KeyQuery>::try_from_uri(&parts.uri).ok();
+        let key = header_key(&parts.headers)
+            .or_else(|| query.as_ref().and_then(|q| q.api_key.as_deref()));
+        state.access.authenticate_key(key).map(StreamClient)
+    }
+}
+
+/// Key of the `x-api-key` or `Authorization: Bearer` header
+fn header_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+}
+
+/// Authentication, rate limits and quotas of the clients
+pub struct Access {
+    /// Keyed by the API key, `None` when authentication is off
+    keys: Option<HashMap<String, Arc<ApiKey>>>,
+    rate_per_minute: f64,
+    burst: f64,
+    /// Keyed by the key name, anonymous clients share the bucket under `""`
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    usage: Mutex<HashMap<String, DailyUsage>>,
+}
+
+impl Access {
+    pub fn new(config: &AppConfig) -> Result<Self, anyhow::Error> {
+        let keys = config
+            .api_keys_file
+            .as_ref()
+            .map(|path| {
+                let keys: Vec<ApiKey> = serde_json::from_slice(&fs::read(path)?)?;
+                info!(message = "Loaded API keys", count = keys.len(), path = ?path);
+                Ok::<_, anyhow::Error>(
+                    keys.into_iter()
+                        .map(|k| (k.key.clone(), Arc::new(k)))
+                        .collect(),
+                )
+            })
+            .transpose()?;
+        Ok(Self {
+            keys,
+            rate_per_minute: config.rate_limit_per_minute,
+            burst: config.rate_limit_burst,
+            buckets: Mutex::default(),
+            usage: Mutex::default(),
+        })
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Client, AccessError> {
+        self.authenticate_key(header_key(headers))
+    }
+
+    fn authenticate_key(&self, key: Option<&str>) -> Result<Client, AccessError> {
+        let Some(keys) = &self.keys else {
+            return Ok(Client(None));
+        };
+        let key = key.ok_or(AccessError::Unauthorized("missing API key"))?;
+        keys.get(key.trim())
+            .cloned()
+            .map(|k| Client(Some(k)))
+            .ok_or_else(|| {
+                warn!(message = "Rejected unknown API key");
+                AccessError::Unauthorized("unknown API key")
+            })
+    }
+
+    /// Takes a token from the client's bucket, a rate of 0 turns rate limiting off
+    pub fn rate_limit(&self, client: &Client) -> Result<(), AccessError> {
+        let key = client.0.as_ref();
+        let rate = key
+            .and_then(|k| k.rate_limit_per_minute)
+            .unwrap_or(self.rate_per_minute);
+        let burst = key
+            .and_then(|k| k.rate_limit_burst)
+            .unwrap_or(self.burst)
+            .max(1.);
+        if rate <= 0. {
+            return Ok(());
+        }
+
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|e| AccessError::Internal(map_poison_error(e)))?;
+        let bucket = buckets
+            .entry(client.name().unwrap_or_default().to_string())
+            .or_insert_with(|| TokenBucket::new(burst));
+        bucket
+            .take(rate / 60., burst)
+            .map_err(|retry_after| AccessError::RateLimited {
+                message: format!("Rate limited to {} image requests per minute", rate),
+                retry_after,
+            })
+    }
+
+    /// Checks the job against the limits of the client and queues it with `queue`, no other job
+    /// of the client is admitted in between. The usage is given back when the job isn't queued.
+    pub fn admit(
+        &self,
+        jobs: &dyn JobStore,
+        client: &Client,
+        cost: u64,
+        queue: impl FnOnce() -> Result<bool, anyhow::Error>,
+    ) -> Result<bool, AccessError> {
+        let _admission = client
+            .0
+            .as_ref()
+            .map(|key| key.admission.lock())
+            .transpose()
+            .map_err(|e| AccessError::Internal(map_poison_error(e)))?;
+        self.reserve(jobs, client, cost)?;
+        let queued = queue();
+        if !matches!(queued, Ok(true)) {
+            self.refund(client.name(), Utc::now().date_naive(), cost);
+        }
+        queued.map_err(AccessError::Internal)
+    }
+
+    /// Checks the concurrent jobs of the client and adds `cost` in pixels times samples to its
+    /// usage today, rejecting it when over the quota
+    fn reserve(&self, jobs: &dyn JobStore, client: &Client, cost: u64) -> Result<(), AccessError> {
+        let Some(key) = &client.0 else {
+            return Ok(());
+        };
+
+        if let Some(max) = key.max_concurrent {
+            let running = jobs
+                .unfinished()
+                .map_err(AccessError::Internal)?
+                .iter()
+                .filter(|(_, job)| job.owner.as_deref() == Some(key.name.as_str()))
+                .count();
+            if running >= max {
+                return Err(AccessError::QuotaExceeded(format!(
+                    "At most {} jobs can be queued or rendering at once",
+                    max
+                )));
+            }
+        }
+
+        let mut usage = self
+            .usage
+            .lock()
+            .map_err(|e| AccessError::Internal(map_poison_error(e)))?;
+        let today = Utc::now().date_naive();
+        let usage = usage.entry(key.name.clone()).or_insert(DailyUsage {
+            day: today,
+            used: 0,
+        });
+        if usage.day != today {
+            *usage = DailyUsage {
+                day: today,
+                used: 0,
+            };
+        }
+        if let Some(quota) = key.daily_quota {
+            if usage.used + cost > quota {
+                return Err(AccessError::QuotaExceeded(format!(
+                    "Daily quota of {} pixels times samples would be exceeded, {} left today",
+                    quota,
+                    quota.saturating_sub(usage.used)
+                )));
+            }
+        }
+        usage.used += cost;
+        Ok(())
+    }
+
+    /// Gives back the usage of a job that failed or was cancelled
+    pub fn refund_job(&self, job: &Job, cost: u64) {
+        self.refund(job.owner.as_deref(), job.created.date_naive(), cost);
+    }
+
+    /// Gives back `cost` to the usage of `owner` on `day`, usage of past days is already reset
+    fn refund(&self, owner: Option<&str>, day: NaiveDate, cost: u64) {
+        let Some(owner) = owner else { return };
+        if let Ok(mut usage) = self.usage.lock() {
+            if let Some(usage) = usage.get_mut(owner).filter(|usage| usage.day == day) {
+                usage.used = usage.used.saturating_sub(cost);
+            }
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64) -> Self {
+        Self {
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    /// Refills at `rate` tokens per second up to `burst` and takes one, otherwise returns the
+    /// seconds until one is available
+    fn take(&mut self, rate: f64, burst: f64) -> Result<(), u64> {
+        let now = Instant::now();
+        self.tokens = (self.tokens + (now - self.last).as_secs_f64() * rate).min(burst);
+        self.last = now;
+        if self.tokens < 1. {
+            return Err(((1. - self.tokens) / rate).ceil() as u64);
+        }
+        self.tokens -= 1.;
+        Ok(())
+    }
+}
+
+/// Pixels times samples a key rendered on `day`, kept in memory so it resets on restart
+struct DailyUsage {
+    day: NaiveDate,
+    used: u64,
+}
+
+#[derive(Debug)]
+pub enum AccessError {
+    Unauthorized(&'static str),
+    RateLimited { message: String, retry_after: u64 },
+    QuotaExceeded(String),
+    Internal(anyhow::Error),
+}
+
+impl IntoResponse for AccessError {
+    fn into_response(self) -> Response {
+        match self {
+            AccessError::Unauthorized(message) => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))],
+                message,
+            )
+                .into_response(),
+            AccessError::RateLimited {
+                message,
+                retry_after,
+            } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, HeaderValue::from(retry_after.max(1)))],
+                message,
+            )
+                .into_response(),
+            AccessError::QuotaExceeded(message) => {
+                (StatusCode::TOO_MANY_REQUESTS, message).into_response()
+            }
+            AccessError::Internal(e) => {
+                error!(message = "Unable to admit request", err = ?e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong").into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use envconfig::Envconfig;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::store::{Job, MemoryStore};
+
+    fn access(keys: bool) -> Access {
+        let mut env = HashMap::new();
+        if keys {
+            env.insert(
+                "API_KEYS_FILE".to_string(),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/api_keys.example.json").to_string(),
+            );
+        }
+        Access::new(&AppConfig::init_from_hashmap(&env).unwrap()).unwrap()
+    }
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn local(access: &Access) -> Client {
+        access
+            .authenticate(&headers(
+                header::HeaderName::from_static(API_KEY_HEADER),
+                "local-dev-key",
+            ))
+            .unwrap()
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(2.);
+        assert!(bucket.take(1., 2.).is_ok());
+        assert!(bucket.take(1., 2.).is_ok());
+        assert_eq!(bucket.take(1., 2.), Err(1));
+
+        bucket.last -= Duration::from_secs(10);
+        assert!(bucket.take(1., 2.).is_ok());
+        assert!(bucket.take(1., 2.).is_ok());
+        // refilling stops at the burst
+        assert!(bucket.take(1., 2.).is_err());
+    }
+
+    #[test]
+    fn authenticates_with_either_header() {
+        let access = access(true);
+        let Err(error) = access.authenticate(&HeaderMap::new()) else {
+            panic!("authenticated without a key");
+        };
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let unknown = headers(header::HeaderName::from_static(API_KEY_HEADER), "nope");
+        assert!(matches!(
+            access.authenticate(&unknown),
+            Err(AccessError::Unauthorized(_))
+        ));
+
+        assert_eq!(local(&access).name(), Some("local"));
+        let bearer = headers(header::AUTHORIZATION, "Bearer unlimited-dev-key");
+        assert_eq!(access.authenticate(&bearer).unwrap().name(), Some("admin"));
+    }
+
+    #[test]
+    fn anonymous_without_keys_file() {
+        let client = access(false).authenticate(&HeaderMap::new()).unwrap();
+        assert_eq!(client.name(), None);
+        assert!(client.owns(Some("local")));
+    }
+
+    #[test]
+    fn rate_limit_answers_too_many_requests() {
+        let access = access(true);
+        let client = local(&access);
+        for _ in 0..5 {
+            assert!(access.rate_limit(&client).is_ok());
+        }
+        let response = access.rate_limit(&client).unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        // every key has its own bucket
+        let admin = access
+            .authenticate(&headers(header::AUTHORIZATION, "Bearer unlimited-dev-key"))
+            .unwrap();
+        assert!(access.rate_limit(&admin).is_ok());
+    }
+
+    #[test]
+    fn daily_quota_is_reserved_refunded_and_rolls_over() {
+        let access = access(true);
+        let jobs = MemoryStore::default();
+        let client = local(&access);
+
+        access.reserve(&jobs, &client, 60_000_000).unwrap();
+        let over = access.reserve(&jobs, &client, 60_000_000).unwrap_err();
+        assert_eq!(over.into_response().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        access.refund(client.name(), Utc::now().date_naive(), 60_000_000);
+        access.reserve(&jobs, &client, 60_000_000).unwrap();
+
+        // usage of a past day no longer counts
+        access.usage.lock().unwrap().get_mut("local").unwrap().day -= chrono::Duration::days(1);
+        access.reserve(&jobs, &client, 60_000_000).unwrap();
+    }
+
+    #[test]
+    fn jobs_are_refunded_on_the_day_they_were_charged() {
+        let access = access(true);
+        let jobs = MemoryStore::default();
+        let client = local(&access);
+        access.reserve(&jobs, &client, 60_000_000).unwrap();
+
+        let mut yesterday = Job::new(serde_json::json!({})).with_owner(Some("local"));
+        yesterday.created -= chrono::Duration::days(1);
+        access.refund_job(&yesterday, 60_000_000);
+        assert!(access.reserve(&jobs, &client, 60_000_000).is_err());
+
+        access.refund_job(
+            &Job::new(serde_json::json!({})).with_owner(Some("local")),
+            60_000_000,
+        );
+        access.reserve(&jobs, &client, 60_000_000).unwrap();
+    }
+
+    #[test]
+    fn concurrent_jobs_are_limited() {
+        let access = access(true);
+        let jobs = MemoryStore::default();
+        let client = local(&access);
+        let queue = |owner: Option<&str>| {
+            let job = Job::new(serde_json::json!({})).with_owner(owner);
+            jobs.insert(Uuid::new_v4(), job).map(|_| true)
+        };
+
+        assert!(access
+            .admit(&jobs, &client, 1, || queue(Some("local")))
+            .unwrap());
+        // jobs of other keys don't count
+        queue(Some("admin")).unwrap();
+        assert!(access
+            .admit(&jobs, &client, 1, || queue(Some("local")))
+            .unwrap());
+        assert!(matches!(
+            access.admit(&jobs, &client, 1, || queue(Some("local"))),
+            Err(AccessError::QuotaExceeded(_))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use std::{path::PathBuf, thread};
+
 use envconfig::Envconfig;
 
 const SHUTTLE_URL: &str = "https://raytracing-iow.shuttleapp.rs";
@@ -7,9 +9,50 @@ const LOCAL_URL: &str = "http://localhost:8000";
 pub struct AppConfig {
     #[envconfig(from = "SHUTTLE", default = "false")]
     pub shuttle: bool,
+
+    /// Jobs rendered at the same time
+    #[envconfig(from = "RENDER_WORKERS", default = "3")]
+    pub render_workers: usize,
+
+    /// Threads each job renders with, 0 splits the cores evenly between the workers
+    #[envconfig(from = "RENDER_THREADS", default = "0")]
+    pub render_threads: usize,
+
+    /// Jobs waiting for a worker before new requests are rejected
+    #[envconfig(from = "MAX_QUEUED", default = "10")]
+    pub max_queued: usize,
+
+    /// Directory to keep jobs in across restarts, jobs are only kept in memory when unset
+    #[envconfig(from = "JOB_STORE_DIR")]
+    pub job_store_dir: Option<PathBuf>,
+
+    /// Hours finished jobs are kept before they are removed
+    #[envconfig(from = "JOB_RETENTION_HOURS", default = "24")]
+    pub job_retention_hours: u64,
+
+    /// JSON file of API keys and their quotas like `shuttle/api_keys.example.json`,
+    /// anyone can submit images when unset
+    #[envconfig(from = "API_KEYS_FILE")]
+    pub api_keys_file: Option<PathBuf>,
+
+    /// Image requests a client can make per minute, anonymous clients share one limit. 0 is unlimited
+    #[envconfig(from = "RATE_LIMIT_PER_MINUTE", default = "10")]
+    pub rate_limit_per_minute: f64,
+
+    /// Image requests a client can make in a row before the per minute limit applies
+    #[envconfig(from = "RATE_LIMIT_BURST", default = "5")]
+    pub rate_limit_burst: f64,
 }
 
 impl AppConfig {
+    pub fn threads_per_job(&self) -> usize {
+        if self.render_threads > 0 {
+            return self.render_threads;
+        }
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        (cores / self.render_workers.max(1)).max(1)
+    }
+
     pub fn root_url(&self) -> &'static str {
         if self.shuttle {
             SHUTTLE_URL
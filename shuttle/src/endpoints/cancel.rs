@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use tokio::task::spawn_blocking;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{access::Client, queue::Cancelled, state::AppData, utils::someting_went_wrong};
+
+/// Cancels a queued or rendering image generation
+#[utoipa::path(
+    delete,
+    path = "/{id}",
+    responses(
+        (status = NO_CONTENT, description = "Removed from the queue"),
+        (status = ACCEPTED, description = "Rendering stops shortly"),
+        (status = NOT_FOUND, description = "Image id not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = CONFLICT, description = "Image generation already finished"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
+    ),
+    security((), ("api_key" = [])),
+)]
+pub async fn cancel_image(
+    State(state): State<AppData>,
+    client: Client,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    spawn_blocking(move || {
+        let job = state.jobs.get(&id).map_err(|e| {
+            error!(message = "Unable to read job", err = ?e);
+            someting_went_wrong()
+        })?;
+        // jobs of other clients are hidden
+        let Some(job) = job.filter(|job| client.owns(job.owner.as_deref())) else {
+            warn!(message = "Image Id not found", id = %id);
+            return Err((StatusCode::NOT_FOUND, "image id not found".to_string()));
+        };
+
+        let cancelled = state.queue.cancel(state.jobs.as_ref(), &id).map_err(|e| {
+            error!(message = "Unable to cancel job", err = ?e);
+            someting_went_wrong()
+        })?;
+        info!(message = "Cancelling Image", id = %id);
+        match cancelled {
+            Cancelled::Dequeued { cost } => {
+                // running renders are refunded by their worker once they stopped
+                state.access.refund_job(&job, cost);
+                Ok(StatusCode::NO_CONTENT)
+            }
+            Cancelled::Stopping => Ok(StatusCode::ACCEPTED),
+            Cancelled::NotActive => Err((
+                StatusCode::CONFLICT,
+                "image generation already finished".to_string(),
+            )),
+        }
+    })
+    .await
+    .map_err(|e| {
+        error!(message = "Unable to join cancel", err = ?e);
+        someting_went_wrong()
+    })?
+}
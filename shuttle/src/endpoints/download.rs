@@ -1,54 +1,181 @@
+use std::io::Cursor;
+
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
+    http::{header, HeaderMap},
     response::{IntoResponse, Redirect},
 };
-use image::RgbImage;
+use image::{codecs::hdr::HdrEncoder, ImageOutputFormat};
+use serde::Deserialize;
 use tokio::task::spawn_blocking;
 use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
-    state::AppData,
-    utils::{anyhow_error_http_response, image_into_response},
+    access::Client,
+    state::{AppData, RenderedImage},
+    utils::anyhow_error_http_response,
 };
 
 use super::status::ImageStatus;
 
-/// Downloads the processed image
+/// Downloads the processed image, it stays available until the job expires.
+/// The format is picked from the `format` query or else the `Accept` header, PNG by default.
 #[utoipa::path(
     get,
     path = "/{id}/download",
+    params(DownloadQuery),
     responses(
-        (status = OK, description = "Image", body = String, content_type = "image/png"),
+        (status = OK, description = "Image", content(
+            ("image/png" = String),
+            ("image/jpeg" = String),
+            ("image/webp" = String),
+            ("image/x-exr" = String),
+            ("image/vnd.radiance" = String)
+        )),
+        (status = BAD_REQUEST, description = "Invalid quality or thumbnail size"),
+        (status = NOT_FOUND, description = "Image id not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = NOT_ACCEPTABLE, description = "None of the accepted formats are supported"),
+        (status = GONE, description = "Image generation was cancelled"),
         (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
     ),
+    security((), ("api_key" = [])),
 )]
 pub async fn download_image(
     State(state): State<AppData>,
+    client: Client,
     Path(id): Path<Uuid>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
 ) -> Result<ImageResponse, DownloadError> {
+    let format = match query.format {
+        Some(format) => format,
+        None => headers
+            .get(header::ACCEPT)
+            .map(|accept| {
+                accept
+                    .to_str()
+                    .ok()
+                    .and_then(OutputFormat::negotiate)
+                    .ok_or_else(|| {
+                        DownloadError::Error((
+                            StatusCode::NOT_ACCEPTABLE,
+                            "supported formats are image/png, image/jpeg, image/webp, \
+                             image/x-exr and image/vnd.radiance"
+                                .to_string(),
+                        ))
+                    })
+            })
+            .transpose()?
+            .unwrap_or(OutputFormat::Png),
+    };
+    let quality = query.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+    if !(1..=100).contains(&quality) {
+        return Err(DownloadError::Error((
+            StatusCode::BAD_REQUEST,
+            format!("Quality: {} has to be between 1 and 100", quality),
+        )));
+    }
+    if query.size == Some(0) {
+        return Err(DownloadError::Error((
+            StatusCode::BAD_REQUEST,
+            "Thumbnail size has to be at least 1 pixel".to_string(),
+        )));
+    }
+
+    spawn_blocking(move || {
+        let job = state
+            .jobs
+            .get(&id)
+            .map_err(|e| {
+                error!(message = "Unable to read job", err = ?e);
+                DownloadError::something_went_wrong()
+            })?
+            // jobs of other clients are hidden
+            .filter(|job| client.owns(job.owner.as_deref()))
+            .ok_or_else(|| {
+                warn!(message = "Image Id not found", id = %id);
+                DownloadError::Error((StatusCode::NOT_FOUND, "image id not found".to_string()))
+            })?;
+        match job.status {
+            ImageStatus::Completed(Ok(c)) => {
+                info!(message = "Finished Image", id = %id);
+                let thumbnail = query.size.map(|size| c.thumbnail(size));
+                format
+                    .encode(thumbnail.as_ref().unwrap_or(&c), quality)
+                    .map(|body| ImageResponse { format, body })
+                    .map_err(|e| {
+                        error!(message = "Error occured during image writing", err = ?e);
+                        DownloadError::something_went_wrong()
+                    })
+            }
+            ImageStatus::Completed(Err(e)) => {
+                Err(DownloadError::Error(anyhow_error_http_response(&e)))
+            }
+            ImageStatus::Cancelled => Err(DownloadError::Error((
+                StatusCode::GONE,
+                "image generation was cancelled".to_string(),
+            ))),
+            _ => Err(DownloadError::Redirect(Redirect::to(&format!("/{}", id)))),
+        }
+    })
+    .await
+    .map_err(|e| {
+        error!(message = "Unable to join status grab", err = ?e);
+        DownloadError::something_went_wrong()
+    })?
+}
+
+/// Downloads the render passes of the image as a multi-layer EXR, the image stays available
+#[utoipa::path(
+    get,
+    path = "/{id}/aovs",
+    responses(
+        (status = OK, description = "Render Passes", body = String, content_type = "image/x-exr"),
+        (status = NOT_FOUND, description = "Image or render passes not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = GONE, description = "Image generation was cancelled"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
+    ),
+    security((), ("api_key" = [])),
+)]
+pub async fn download_aovs(
+    State(state): State<AppData>,
+    client: Client,
+    Path(id): Path<Uuid>,
+) -> Result<AovsResponse, DownloadError> {
     spawn_blocking(move || {
-        let mut img_gen = state.img_gen.lock().map_err(|_e| {
-            error!(message = "Unable to lock status");
-            DownloadError::something_went_wrong()
-        })?;
-        let status = img_gen.remove(&id).ok_or_else(|| {
-            warn!(message = "Image Id not found", id = %id);
-            DownloadError::Error((StatusCode::NOT_FOUND, "image id not found".to_string()))
-        })?;
-        match status {
-            ImageStatus::Completed(c) => c
-                .map(|c| {
-                    info!(message = "Finished Image", id = %id);
-                    ImageResponse(c)
-                })
-                .map_err(|e| DownloadError::Error(anyhow_error_http_response(&e))),
-            s => {
-                let redirect_uri = format!("/{}", id);
-                img_gen.insert(id, s);
-                Err(DownloadError::Redirect(Redirect::to(&redirect_uri)))
+        let job = state
+            .jobs
+            .get(&id)
+            .map_err(|e| {
+                error!(message = "Unable to read job", err = ?e);
+                DownloadError::something_went_wrong()
+            })?
+            // jobs of other clients are hidden
+            .filter(|job| client.owns(job.owner.as_deref()))
+            .ok_or_else(|| {
+                warn!(message = "Image Id not found", id = %id);
+                DownloadError::Error((StatusCode::NOT_FOUND, "image id not found".to_string()))
+            })?;
+        match job.status {
+            ImageStatus::Completed(Ok(c)) => c.aovs.clone().map(AovsResponse).ok_or_else(|| {
+                DownloadError::Error((
+                    StatusCode::NOT_FOUND,
+                    "no render passes were requested".to_string(),
+                ))
+            }),
+            ImageStatus::Completed(Err(e)) => {
+                Err(DownloadError::Error(anyhow_error_http_response(&e)))
             }
+            ImageStatus::Cancelled => Err(DownloadError::Error((
+                StatusCode::GONE,
+                "image generation was cancelled".to_string(),
+            ))),
+            _ => Err(DownloadError::Redirect(Redirect::to(&format!("/{}", id)))),
         }
     })
     .await
@@ -58,14 +185,130 @@ pub async fn download_image(
     })?
 }
 
-pub struct ImageResponse(RgbImage);
+pub struct AovsResponse(Vec<u8>);
+
+impl IntoResponse for AovsResponse {
+    fn into_response(self) -> axum::response::Response {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("image/x-exr"),
+        );
+        headers.insert(header::CONTENT_LENGTH, self.0.len().into());
+        (StatusCode::OK, headers, self.0).into_response()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadQuery {
+    /// Overrides the `Accept` header
+    format: Option<OutputFormat>,
+    /// JPEG quality from 1 to 100
+    #[param(default = 90, minimum = 1, maximum = 100)]
+    quality: Option<u8>,
+    /// Scales the image down to fit within this many pixels
+    #[param(minimum = 1)]
+    size: Option<u32>,
+}
+
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+#[derive(Deserialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    /// 16 bits per channel PNG
+    Png16,
+    Jpeg,
+    /// Lossless WebP
+    Webp,
+    /// Linear float OpenEXR without the output transform
+    Exr,
+    /// Linear Radiance HDR without the output transform
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Best supported format of an `Accept` header, 16 bit PNG is only available through the query
+    fn negotiate(accept: &str) -> Option<Self> {
+        let mut ranges: Vec<(f32, Option<Self>)> = accept
+            .split(',')
+            .map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                let format = match params.next().unwrap_or_default() {
+                    "image/png" | "image/*" | "*/*" => Some(Self::Png),
+                    "image/jpeg" => Some(Self::Jpeg),
+                    "image/webp" => Some(Self::Webp),
+                    "image/x-exr" | "image/aces" => Some(Self::Exr),
+                    "image/vnd.radiance" => Some(Self::Hdr),
+                    _ => None,
+                };
+                let q = params
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.);
+                (q, format)
+            })
+            .filter(|(q, _)| *q > 0.)
+            .collect();
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.into_iter().find_map(|(_, format)| format)
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Png | OutputFormat::Png16 => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Exr => "image/x-exr",
+            OutputFormat::Hdr => "image/vnd.radiance",
+        }
+    }
+
+    fn encode(&self, image: &RenderedImage, quality: u8) -> Result<Vec<u8>, anyhow::Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        match self {
+            OutputFormat::Png => image
+                .image()
+                .write_to(&mut buffer, ImageOutputFormat::Png)?,
+            OutputFormat::Png16 => image
+                .image16()
+                .write_to(&mut buffer, ImageOutputFormat::Png)?,
+            OutputFormat::Jpeg => image
+                .image()
+                .write_to(&mut buffer, ImageOutputFormat::Jpeg(quality))?,
+            OutputFormat::Webp => image
+                .image()
+                .write_to(&mut buffer, ImageOutputFormat::WebP)?,
+            OutputFormat::Exr => image
+                .linear
+                .write_to(&mut buffer, ImageOutputFormat::OpenExr)?,
+            OutputFormat::Hdr => HdrEncoder::new(&mut buffer).encode(
+                &image.linear.pixels().copied().collect::<Vec<_>>(),
+                image.linear.width() as usize,
+                image.linear.height() as usize,
+            )?,
+        }
+        Ok(buffer.into_inner())
+    }
+}
+
+pub struct ImageResponse {
+    format: OutputFormat,
+    body: Vec<u8>,
+}
 
 impl IntoResponse for ImageResponse {
     fn into_response(self) -> axum::response::Response {
-        image_into_response(self.0).unwrap_or_else(|e| {
-            error!(message = "Error occured during image writing", err = ?e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Error downloading image").into_response()
-        })
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(self.format.content_type()),
+        );
+        headers.insert(header::CONTENT_LENGTH, self.body.len().into());
+        headers.insert(header::VARY, header::HeaderValue::from_static("accept"));
+        (StatusCode::OK, headers, self.body).into_response()
     }
 }
 
@@ -0,0 +1,203 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    access::StreamClient,
+    endpoints::status::{status_response, with_queue_position, ImageStatus, StatusBody},
+    events::JobEvent,
+    state::{AppData, CompletedImageGen},
+    utils::someting_went_wrong,
+};
+
+/// Streams the status of an image generation as Server-Sent Events.
+///
+/// `status` events carry the same body as `GET /{id}` and `preview` events a low resolution
+/// frame after every progressive pass. A failed render sends an `error` event. The stream ends
+/// once the image is completed, failed or cancelled.
+///
+/// Browsers can't send headers with `EventSource`, the API key can be given as the `api_key`
+/// query parameter instead.
+#[utoipa::path(
+    get,
+    path = "/{id}/events",
+    responses(
+        (status = OK, description = "Event Stream", body = String, content_type = "text/event-stream"),
+        (status = NOT_FOUND, description = "Image id not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
+    ),
+    params(("api_key" = Option<String>, Query, description = "API key when it can't be sent as a header")),
+    security((), ("api_key" = []), ("api_key_query" = [])),
+)]
+pub async fn image_events(
+    State(state): State<AppData>,
+    StreamClient(client): StreamClient,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    // subscribe before reading the status so no change is missed in between
+    let events = state.events.subscribe();
+    let job = state
+        .jobs
+        .get(&id)
+        .map_err(|e| {
+            error!(message = "Unable to read job", err = ?e);
+            someting_went_wrong()
+        })?
+        // jobs of other clients are hidden
+        .filter(|job| client.owns(job.owner.as_deref()))
+        .ok_or_else(|| {
+            warn!(message = "Status Not found");
+            (StatusCode::NOT_FOUND, "image id not found".to_string())
+        })?;
+
+    let first = status_event(&state, &id, job.status);
+    let stream = stream::unfold(
+        (Some(first), events, state, false),
+        move |(next, mut events, state, done)| async move {
+            if let Some((event, finished)) = next {
+                return Some((Ok(event), (None, events, state, finished)));
+            }
+            if done {
+                return None;
+            }
+            loop {
+                match events.recv().await {
+                    Ok((event_id, event)) if event_id == id => {
+                        let (event, finished) = match event {
+                            JobEvent::Status(status) => status_event(&state, &id, status),
+                            JobEvent::Preview(frame) => (json_event("preview", &*frame), false),
+                            JobEvent::QueueMoved => continue,
+                        };
+                        return Some((Ok(event), (None, events, state, finished)));
+                    }
+                    Ok((_, JobEvent::QueueMoved)) => {
+                        // only queued jobs have a position that moves
+                        if let Ok(Some(job)) = state.jobs.get(&id) {
+                            if let ImageStatus::Queued(_) = job.status {
+                                let (event, finished) = status_event(&state, &id, job.status);
+                                return Some((Ok(event), (None, events, state, finished)));
+                            }
+                        }
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        // the missed events may have held the final status, send the current one
+                        warn!(message = "Event stream fell behind", id = %id, skipped);
+                        let job = match state.jobs.get(&id) {
+                            Ok(Some(job)) => job,
+                            Ok(None) => return None,
+                            Err(e) => {
+                                error!(message = "Unable to read job", err = ?e);
+                                return None;
+                            }
+                        };
+                        let (event, finished) = status_event(&state, &id, job.status);
+                        return Some((Ok(event), (None, events, state, finished)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Low resolution frame of an image while it renders
+#[derive(Serialize, ToSchema)]
+pub struct PreviewFrame {
+    /// Finished progressive pass from 1
+    pub pass: u32,
+    pub passes: u32,
+    pub width: u32,
+    pub height: u32,
+    /// PNG data URL
+    pub image: String,
+}
+
+/// Event for the status and whether the stream is finished after it
+fn status_event(
+    state: &AppData,
+    id: &Uuid,
+    status: ImageStatus<CompletedImageGen>,
+) -> (Event, bool) {
+    let status = match with_queue_position(state, id, status) {
+        Ok(status) => status,
+        Err(e) => return (Event::default().event("error").data(e.to_string()), true),
+    };
+    match status_response(&state.config, id, status) {
+        Ok(status) => {
+            let finished = matches!(status, ImageStatus::Completed(_) | ImageStatus::Cancelled);
+            (json_event("status", &StatusBody(&status)), finished)
+        }
+        Err((_, message)) => (Event::default().event("error").data(message), true),
+    }
+}
+
+fn json_event<T: Serialize>(name: &str, data: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{body::HttpBody, response::IntoResponse, Json};
+    use envconfig::Envconfig;
+    use image::Rgb32FImage;
+
+    use super::*;
+    use crate::{
+        config::AppConfig,
+        endpoints::status::{QueuePosition, Rendering},
+        state::RenderedImage,
+    };
+
+    #[test]
+    fn status_events_carry_the_status_body() {
+        let config = AppConfig::init_from_hashmap(&HashMap::new()).unwrap();
+        let id = Uuid::new_v4();
+        let image = Arc::new(RenderedImage {
+            linear: Rgb32FImage::new(1, 1),
+            output: serde_json::from_str("{}").unwrap(),
+            aovs: None,
+            stats: None,
+        });
+        let statuses: [ImageStatus<CompletedImageGen>; 4] = [
+            ImageStatus::Queued(QueuePosition::default()),
+            ImageStatus::Rendering(Rendering::new(1)),
+            ImageStatus::Completed(Ok(image)),
+            ImageStatus::Cancelled,
+        ];
+
+        for status in statuses {
+            let event = status_response(&config, &id, status.clone()).ok().unwrap();
+            let event = serde_json::to_value(StatusBody(&event)).unwrap();
+
+            let get = status_response(&config, &id, status.clone()).ok().unwrap();
+            let mut body = get.map_completed(Json).into_response().into_body();
+            let get = futures::executor::block_on(body.data()).unwrap().unwrap();
+            let get: serde_json::Value = serde_json::from_slice(&get).unwrap();
+
+            assert_eq!(event, get);
+            if let ImageStatus::Completed(_) = status {
+                // the UI downloads the image from the link in the status
+                assert!(event["download_url"].is_string());
+            }
+        }
+    }
+}
@@ -1,6 +1,15 @@
+use std::{ops::RangeInclusive, sync::Arc};
+
 use anyhow::anyhow;
-use axum::{extract::State, http::StatusCode, Json};
-use raytracing_iow::render::camera::CameraConfig;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use raytracing_iow::render::{
+    aov::Aov, camera::CameraConfig, filter::Filter, post::Effect, viewport::ViewportConfig,
+};
 use serde::{Deserialize, Serialize};
 use tokio::task::spawn_blocking;
 use tracing::{error, info};
@@ -8,82 +17,103 @@ use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
+    access::Client,
     endpoints::status::ImageStatus,
-    models::{Color, Material, Object, Shape, Sphere},
-    render::render_img,
+    models::{Background, Color, Fog, Material, Object, Output, Shape, Sphere},
     state::AppData,
+    store::Job,
     utils::someting_went_wrong,
 };
 
-const MAX_PROCESSING: usize = 3;
 const MAX_DIM: u32 = 3000;
 const MAX_SAMPLES: u32 = 500;
 const MAX_DEPTH: u32 = 50;
+/// Filter radius in pixels, every sample is splatted onto all pixels within it
+const MAX_FILTER_RADIUS: f64 = 4.;
+/// Bloom radius as a fraction of the image height, the blur kernel grows with it
+const MAX_BLOOM_RADIUS: f64 = 0.05;
 
 /// Starts the generation of an image
 #[utoipa::path(
     post,
     path = "/",
+    request_body = GenImageRequest,
     responses(
         (status = OK, description = "Image Generation Started", body = GenImageResponse),
-        (status = TOO_MANY_REQUESTS, description = "Too many image generation requests come back later"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = TOO_MANY_REQUESTS, description = "Rate limited, over quota or the render queue is full"),
         (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
     ),
+    security((), ("api_key" = [])),
 )]
 pub async fn gen_image(
     State(state): State<AppData>,
-    Json(req): Json<GenImageRequest>,
-) -> Result<Json<GenImageResponse>, (StatusCode, String)> {
+    client: Client,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<GenImageResponse>, Response> {
+    submit(state, client, body).await.map(Json)
+}
+
+/// Validates and queues a request body as a new job of the client
+pub async fn submit(
+    state: AppData,
+    client: Client,
+    body: serde_json::Value,
+) -> Result<GenImageResponse, Response> {
+    state
+        .access
+        .rate_limit(&client)
+        .map_err(IntoResponse::into_response)?;
+    // the raw body is kept with the job so it can be rendered again after a restart
+    let req: GenImageRequest = serde_json::from_value(body.clone())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response())?;
     req.validate()
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+    let cost = req.cost();
     let new_id = Uuid::new_v4();
 
-    info!(message = "Starting New Image Request", id = %new_id);
+    info!(message = "Queueing New Image Request", id = %new_id, client = ?client.name());
     let queue_state = state.clone();
-    spawn_blocking(move || queue_req(new_id, queue_state))
-        .await
-        .map_err(|e| {
-            error!(message = "Failed to join queue image gen handler", err = ?e);
-            someting_went_wrong()
-        })??;
-
-    info!(message = "Spawning Generating Image");
-    let render_state = state.clone();
-    spawn_blocking(move || {
-        if let Err(e) = render_img(&render_state, &new_id, req) {
-            error!(message = "Error when processing image", err = ?e);
-            match render_state.img_gen.lock() {
-                Ok(mut img_gen) => {
-                    if let Some(status) = img_gen.get_mut(&new_id) {
-                        *status = ImageStatus::Completed(Err(e))
-                    }
-                }
-                Err(e) => {
-                    error!(message = "Failed to get write lock for queueing image gen", err = ?e)
-                }
-            }
-        }
-    });
+    let queued = spawn_blocking(move || {
+        let jobs = queue_state.jobs.as_ref();
+        queue_state.access.admit(jobs, &client, cost, || {
+            let job = Job::new(body).with_owner(client.name());
+            queue_state.queue.push(jobs, new_id, job, req)
+        })
+    })
+    .await
+    .map_err(|e| {
+        error!(message = "Failed to join queue image gen handler", err = ?e);
+        someting_went_wrong().into_response()
+    })?
+    .map_err(IntoResponse::into_response)?;
+    if !queued {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many image gen requests come back later".to_string(),
+        )
+            .into_response());
+    }
 
     let status_url = format!("{}/{}", state.config.root_url(), new_id);
-    Ok(Json(GenImageResponse {
+    Ok(GenImageResponse {
         id: new_id,
         status_url,
-    }))
+    })
 }
 
-fn queue_req(id: Uuid, state: AppData) -> Result<(), (StatusCode, String)> {
-    let mut img_gen = state.img_gen.lock().map_err(|e| {
-        error!(message = "Failed to get write lock for queueing image gen", err = ?e);
-        someting_went_wrong()
-    })?;
-    if img_gen.len() > MAX_PROCESSING {
-        return Err((
-            StatusCode::TOO_MANY_REQUESTS,
-            "Too many image gen requests come back later".to_string(),
-        ));
+/// Queues the jobs that were still queued or rendering when the service stopped
+pub fn resume_jobs(state: &AppData) -> Result<(), anyhow::Error> {
+    let mut unfinished = state.jobs.unfinished()?;
+    unfinished.sort_by_key(|(_, job)| job.created);
+    for (id, job) in unfinished {
+        match serde_json::from_value::<GenImageRequest>(job.request) {
+            Ok(req) => state.queue.resume(id, req)?,
+            Err(e) => state
+                .jobs
+                .set_status(&id, ImageStatus::Completed(Err(Arc::new(e.into()))))?,
+        }
     }
-    img_gen.insert(id, ImageStatus::Queued);
     Ok(())
 }
 
@@ -103,10 +133,19 @@ pub struct GenImageRequest {
         "up": { "x": 0., "y": 1., "z": 0.},
         "defocus_angle": 0.6,
         "focus_dist": 10.0,
+        "spectral": false,
+        "lens": null,
+        "filter": { "Box": { "radius": 0.5 } },
+        "seed": null,
     }))]
     #[serde(default = "default_camera_config")]
     pub camera_config: CameraConfig,
 
+    /// Camera projection
+    #[schema(default = json!({ "Fov": { "vertical_fov": 20.0 } }))]
+    #[serde(default = "default_viewport")]
+    pub viewport: ViewportConfig,
+
     #[schema(default = json!([
         {
             "material": {"Lambertain": { "color": { "r": 0.9765625, "g": 0.47265625, "b": 0.13671875 }}},
@@ -127,9 +166,48 @@ pub struct GenImageRequest {
     ]))]
     #[serde(default = "default_objects")]
     pub objects: Vec<Object>,
+
+    /// Optional atmosphere filling the whole scene
+    #[serde(default)]
+    pub fog: Option<Fog>,
+
+    /// Light coming from outside the scene, defaults to the sky
+    #[schema(example = json!({ "Gradient": {
+        "bottom": { "r": 1.0, "g": 0.6, "b": 0.3 },
+        "top": { "r": 0.2, "g": 0.3, "b": 0.8 }
+    }}))]
+    #[serde(default)]
+    pub background: Option<Background>,
+
+    /// Render passes to write into a multi-layer EXR next to the image
+    #[schema(example = json!(["Depth", "Normal", "Albedo"]))]
+    #[serde(default)]
+    pub aovs: Vec<Aov>,
+
+    /// Denoise the finished image with the given strength from 0 to 1
+    #[schema(example = 1.0, maximum = 1, minimum = 0)]
+    #[serde(default)]
+    pub denoise: Option<f64>,
+
+    /// Effects applied in order to the finished image
+    #[schema(example = json!([
+        { "Bloom": { "threshold": 0.8, "intensity": 0.3, "radius": 0.02 } },
+        { "Vignette": { "strength": 0.5 } }
+    ]))]
+    #[serde(default)]
+    pub post_processing: Vec<Effect>,
+
+    #[schema(default = json!({ "gamma": 1.0, "tone_map": "Clamp" }))]
+    #[serde(default)]
+    pub output: Output,
 }
 
 impl GenImageRequest {
+    /// Pixels times samples per pixel, what the daily quota counts
+    pub fn cost(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.camera_config.samples_per_pixel as u64
+    }
+
     pub fn validate(&self) -> Result<(), anyhow::Error> {
         if self.width > MAX_DIM || self.width < 1 || self.height > MAX_DIM || self.height < 1 {
             return Err(anyhow!(
@@ -149,15 +227,158 @@ impl GenImageRequest {
         if self.camera_config.max_depth > MAX_DEPTH {
             return Err(anyhow!(
                 "Max Depth too big: {} out of {}",
-                self.camera_config.samples_per_pixel,
+                self.camera_config.max_depth,
                 MAX_DEPTH
             ));
         }
+        let radius = self.camera_config.filter.radius();
+        if !(radius > 0. && radius <= MAX_FILTER_RADIUS) {
+            return Err(anyhow!(
+                "Filter radius: {} has to be greater than 0 and at most {}",
+                radius,
+                MAX_FILTER_RADIUS
+            ));
+        }
+        if let Filter::Gaussian { alpha, .. } = self.camera_config.filter {
+            if !alpha.is_finite() || alpha <= 0. {
+                return Err(anyhow!(
+                    "Gaussian filter alpha: {} has to be greater than 0",
+                    alpha
+                ));
+            }
+        }
+        if let Some(lens) = &self.camera_config.lens {
+            let positive = |v: f64| v.is_finite() && v > 0.;
+            if !positive(lens.focal_length)
+                || !positive(lens.f_stop)
+                || !positive(lens.sensor_height)
+            {
+                return Err(anyhow!(
+                    "Lens focal length: {}, f-stop: {} and sensor height: {} have to be greater than 0",
+                    lens.focal_length,
+                    lens.f_stop,
+                    lens.sensor_height,
+                ));
+            }
+            let pos = self.camera_config.pos;
+            let forward = (self.camera_config.look_at - pos).normalize();
+            if !positive(lens.focus_dist(pos, forward)) {
+                return Err(anyhow!("Lens focus has to be in front of the camera"));
+            }
+        }
+        validate_viewport(&self.viewport)?;
+
+        for object in &self.objects {
+            object.shape.validate()?;
+        }
+        if let Some(fog) = &self.fog {
+            fog.validate()?;
+        }
+
+        if let Some(strength) = self.denoise {
+            if !(0. ..=1.).contains(&strength) {
+                return Err(anyhow!(
+                    "Denoise strength: {} has to be between 0 and 1",
+                    strength
+                ));
+            }
+        }
+
+        for effect in &self.post_processing {
+            validate_effect(effect)?;
+        }
+
+        if self.output.gamma <= 0. {
+            return Err(anyhow!(
+                "Gamma: {} has to be greater than 0",
+                self.output.gamma
+            ));
+        }
 
         Ok(())
     }
 }
 
+fn validate_viewport(viewport: &ViewportConfig) -> Result<(), anyhow::Error> {
+    let height = |height: f64| {
+        if height.is_finite() && height > 0. {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Viewport height: {} has to be finite and greater than 0",
+                height
+            ))
+        }
+    };
+    // the projections can't look further than straight back or straight up and down
+    let fov = |name: &str, fov: f64, max: f64, max_included: bool| {
+        if fov > 0. && (fov < max || max_included && fov == max) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{}: {} has to be greater than 0 and {} {} degrees",
+                name,
+                fov,
+                if max_included { "at most" } else { "less than" },
+                max
+            ))
+        }
+    };
+    match *viewport {
+        ViewportConfig::Standard { height: h } | ViewportConfig::Orthographic { height: h } => {
+            height(h)
+        }
+        ViewportConfig::Fov { vertical_fov } => fov("Vertical fov", vertical_fov, 180., false),
+        ViewportConfig::Equirectangular => Ok(()),
+        ViewportConfig::Fisheye { fov: f, .. } => fov("Fisheye fov", f, 360., true),
+        ViewportConfig::Cylindrical {
+            horizontal_fov,
+            vertical_fov,
+        } => {
+            fov("Horizontal fov", horizontal_fov, 360., true)?;
+            fov("Vertical fov", vertical_fov, 180., false)
+        }
+    }
+}
+
+fn validate_effect(effect: &Effect) -> Result<(), anyhow::Error> {
+    let in_range = |name: &str, value: f64, range: RangeInclusive<f64>| {
+        if range.contains(&value) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{}: {} has to be between {} and {}",
+                name,
+                value,
+                range.start(),
+                range.end()
+            ))
+        }
+    };
+    match effect {
+        Effect::Bloom {
+            threshold,
+            intensity,
+            radius,
+        } => {
+            in_range("Bloom threshold", *threshold, 0. ..=100.)?;
+            in_range("Bloom intensity", *intensity, 0. ..=10.)?;
+            in_range("Bloom radius", *radius, 0. ..=MAX_BLOOM_RADIUS)
+        }
+        Effect::Vignette { strength } => in_range("Vignette strength", *strength, 0. ..=1.),
+        Effect::ChromaticAberration { strength } => {
+            in_range("Chromatic aberration strength", *strength, -0.1..=0.1)
+        }
+        Effect::Grain { amount } => in_range("Grain amount", *amount, 0. ..=1.),
+        Effect::ColorGrade(grade) => {
+            in_range("Exposure", grade.exposure, -20. ..=20.)?;
+            in_range("Temperature", grade.temperature, 1000. ..=40000.)?;
+            in_range("Contrast", grade.contrast, 0. ..=10.)?;
+            in_range("Saturation", grade.saturation, 0. ..=10.)
+        }
+    }
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct GenImageResponse {
     id: Uuid,
@@ -173,9 +394,17 @@ fn default_camera_config() -> CameraConfig {
         up: (0., 1., 0.).into(),
         defocus_angle: 0.6,
         focus_dist: 10.0,
+        spectral: false,
+        lens: None,
+        filter: Filter::default(),
+        seed: None,
     }
 }
 
+fn default_viewport() -> ViewportConfig {
+    ViewportConfig::Fov { vertical_fov: 20.0 }
+}
+
 fn default_objects() -> Vec<Object> {
     vec![
         Object {
@@ -190,20 +419,22 @@ fn default_objects() -> Vec<Object> {
                     b: 0.13671875,
                 },
             },
+            opacity: None,
+            back_face: None,
         },
         Object {
             shape: Shape::Sphere(Sphere::Stationary {
                 center: (0., 1., 0.).into(),
                 radius: 1.,
             }),
-            material: Material::Metal {
-                color: Color {
-                    r: 0.,
-                    g: 0.,
-                    b: 0.,
-                },
-                fuzziness: 0.,
+            material: Material::Dielectric {
+                index_of_refraction: 1.5,
+                absorption: None,
+                roughness: 0.,
+                dispersion: 0.,
             },
+            opacity: None,
+            back_face: None,
         },
         Object {
             shape: Shape::Sphere(Sphere::Stationary {
@@ -217,6 +448,8 @@ fn default_objects() -> Vec<Object> {
                     b: 158. / 256., //0.6171875
                 },
             },
+            opacity: None,
+            back_face: None,
         },
         Object {
             shape: Shape::Sphere(Sphere::Stationary {
@@ -231,6 +464,8 @@ fn default_objects() -> Vec<Object> {
                 },
                 fuzziness: 0.,
             },
+            opacity: None,
+            back_face: None,
         },
     ]
 }
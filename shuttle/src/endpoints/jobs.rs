@@ -0,0 +1,226 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    access::Client,
+    endpoints::{
+        gen::{submit, GenImageResponse},
+        status::ImageStatus,
+    },
+    state::{AppData, CompletedImageGen},
+    store::Job,
+    utils::{merge_patch, someting_went_wrong},
+};
+
+const MAX_PER_PAGE: usize = 100;
+
+/// Lists the jobs newest first
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    params(JobsQuery),
+    responses(
+        (status = OK, description = "Page of jobs", body = JobList),
+        (status = BAD_REQUEST, description = "Invalid page"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
+    ),
+    security((), ("api_key" = [])),
+)]
+pub async fn list_jobs(
+    State(state): State<AppData>,
+    client: Client,
+    Query(query): Query<JobsQuery>,
+) -> Result<Json<JobList>, (StatusCode, String)> {
+    if query.page < 1 || !(1..=MAX_PER_PAGE).contains(&query.per_page) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Page has to be at least 1 and per page between 1 and {}",
+                MAX_PER_PAGE
+            ),
+        ));
+    }
+
+    spawn_blocking(move || {
+        let jobs = state.jobs.list().map_err(|e| {
+            error!(message = "Unable to list jobs", err = ?e);
+            someting_went_wrong()
+        })?;
+        let jobs: Vec<(Uuid, Job)> = jobs
+            .into_iter()
+            .filter(|(_, job)| {
+                client.owns(job.owner.as_deref())
+                    && query
+                        .status
+                        .is_none_or(|status| JobState::of(&job.status) == status)
+            })
+            .collect();
+
+        Ok(Json(JobList {
+            total: jobs.len(),
+            page: query.page,
+            per_page: query.per_page,
+            jobs: jobs
+                .into_iter()
+                .skip((query.page - 1).saturating_mul(query.per_page))
+                .take(query.per_page)
+                .map(|(id, job)| JobSummary::new(&state, id, job))
+                .collect(),
+        }))
+    })
+    .await
+    .map_err(|e| {
+        error!(message = "Unable to join job listing", err = ?e);
+        someting_went_wrong()
+    })?
+}
+
+/// Queues the request of a past job again as a new job of the client, with the body merged into it.
+/// Objects are merged key by key, `null` removes a key and arrays are replaced, send `{}`
+/// to render the same request again.
+#[utoipa::path(
+    post,
+    path = "/{id}/resubmit",
+    request_body = Object,
+    responses(
+        (status = OK, description = "Image Generation Started", body = GenImageResponse),
+        (status = NOT_FOUND, description = "Image id not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
+        (status = TOO_MANY_REQUESTS, description = "Rate limited, over quota or the render queue is full"),
+        (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
+    ),
+    security((), ("api_key" = [])),
+)]
+pub async fn resubmit_job(
+    State(state): State<AppData>,
+    client: Client,
+    Path(id): Path<Uuid>,
+    Json(changes): Json<serde_json::Value>,
+) -> Result<Json<GenImageResponse>, Response> {
+    let jobs_state = state.clone();
+    let job = spawn_blocking(move || jobs_state.jobs.get(&id))
+        .await
+        .map_err(|e| {
+            error!(message = "Unable to join job lookup", err = ?e);
+            someting_went_wrong().into_response()
+        })?
+        .map_err(|e| {
+            error!(message = "Unable to read job", err = ?e);
+            someting_went_wrong().into_response()
+        })?
+        // jobs of other clients are hidden
+        .filter(|job| client.owns(job.owner.as_deref()))
+        .ok_or_else(|| {
+            warn!(message = "Image Id not found", id = %id);
+            (StatusCode::NOT_FOUND, "image id not found".to_string()).into_response()
+        })?;
+
+    let mut request = job.request;
+    merge_patch(&mut request, changes);
+    info!(message = "Resubmitting Image Request", id = %id);
+    submit(state, client, request).await.map(Json)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobsQuery {
+    /// Only jobs in this state
+    status: Option<JobState>,
+    /// Page number starting from 1
+    #[param(default = 1, minimum = 1)]
+    #[serde(default = "default_page")]
+    page: usize,
+    #[param(default = 20, minimum = 1, maximum = 100)]
+    #[serde(default = "default_per_page")]
+    per_page: usize,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    20
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobList {
+    pub jobs: Vec<JobSummary>,
+    pub page: usize,
+    pub per_page: usize,
+    /// Jobs matching the filter over all pages
+    pub total: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobSummary {
+    pub id: Uuid,
+    pub status_url: String,
+    pub state: JobState,
+    /// From 0 to 1, missing for failed and cancelled jobs
+    pub progress: Option<f64>,
+    pub width: u32,
+    pub height: u32,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+impl JobSummary {
+    fn new(state: &AppData, id: Uuid, job: Job) -> Self {
+        let dimension = |key: &str| {
+            job.request
+                .get(key)
+                .and_then(serde_json::Value::as_u64)
+                .unwrap_or_default() as u32
+        };
+        let progress = match &job.status {
+            ImageStatus::Queued(_) => Some(0.),
+            ImageStatus::Rendering(r) => Some(r.cur_pixel as f64 / r.max_pixels.max(1) as f64),
+            ImageStatus::Completed(Ok(_)) => Some(1.),
+            ImageStatus::Completed(Err(_)) | ImageStatus::Cancelled => None,
+        };
+        Self {
+            id,
+            status_url: format!("{}/{}", state.config.root_url(), id),
+            state: JobState::of(&job.status),
+            progress,
+            width: dimension("width"),
+            height: dimension("height"),
+            created: job.created,
+            updated: job.updated,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Rendering,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn of(status: &ImageStatus<CompletedImageGen>) -> Self {
+        match status {
+            ImageStatus::Queued(_) => JobState::Queued,
+            ImageStatus::Rendering(_) => JobState::Rendering,
+            ImageStatus::Completed(Ok(_)) => JobState::Completed,
+            ImageStatus::Completed(Err(_)) => JobState::Failed,
+            ImageStatus::Cancelled => JobState::Cancelled,
+        }
+    }
+}
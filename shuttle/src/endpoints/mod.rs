@@ -1,3 +1,6 @@
+pub mod cancel;
 pub mod download;
+pub mod events;
 pub mod gen;
+pub mod jobs;
 pub mod status;
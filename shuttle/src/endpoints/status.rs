@@ -1,4 +1,4 @@
-use std::{collections::hash_map::Entry, time::Duration};
+use std::time::Duration;
 
 use axum::{
     extract::{Path, State},
@@ -7,14 +7,17 @@ use axum::{
     Json,
 };
 use chrono::{DateTime, Utc};
-use serde::{Serialize, Serializer};
+use raytracing_iow::render::stats::RenderStats;
+use serde::{Deserialize, Serialize, Serializer};
 use tokio::task::spawn_blocking;
 use tracing::{error, warn};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
-    state::AppData,
+    access::Client,
+    config::AppConfig,
+    state::{AppData, CompletedImageGen},
     utils::{anyhow_error_http_response, someting_went_wrong},
 };
 
@@ -24,48 +27,36 @@ use crate::{
     path = "/{id}",
     responses(
         (status = OK, description = "Image Status", body = ImageStatusResponse),
+        (status = NOT_FOUND, description = "Image id not found"),
+        (status = UNAUTHORIZED, description = "Missing or unknown API key"),
         (status = INTERNAL_SERVER_ERROR, description = "Internal Server Error")
     ),
+    security((), ("api_key" = [])),
 )]
 pub async fn image_status(
     State(state): State<AppData>,
+    client: Client,
     Path(id): Path<Uuid>,
 ) -> Result<ImageStatus<Json<CompletedImageResponse>>, (StatusCode, String)> {
     spawn_blocking(move || {
-        let mut img_gen = state.img_gen.lock().map_err(|_e| {
-            error!(message = "Unable to lock status");
+        let job = state
+            .jobs
+            .get(&id)
+            .map_err(|e| {
+                error!(message = "Unable to read job", err = ?e);
+                someting_went_wrong()
+            })?
+            // jobs of other clients are hidden
+            .filter(|job| client.owns(job.owner.as_deref()))
+            .ok_or_else(|| {
+                warn!(message = "Status Not found");
+                (StatusCode::NOT_FOUND, "image id not found".to_string())
+            })?;
+        let status = with_queue_position(&state, &id, job.status).map_err(|e| {
+            error!(message = "Unable to find queue position", err = ?e);
             someting_went_wrong()
         })?;
-        match img_gen.entry(id) {
-            Entry::Occupied(o) => {
-                let status = o
-                    .get()
-                    .as_ref()
-                    .map_completed(|c| {
-                        c.as_ref()
-                            .map(|_| {
-                                Json(CompletedImageResponse {
-                                    download_url: format!(
-                                        "{}/{}/download",
-                                        state.config.root_url(),
-                                        id
-                                    ),
-                                })
-                            })
-                            .map_err(anyhow_error_http_response)
-                    })
-                    .transpose_complete();
-
-                if status.is_err() {
-                    o.remove_entry();
-                }
-                status
-            }
-            Entry::Vacant(_) => {
-                warn!(message = "Status Not found");
-                Err((StatusCode::NOT_FOUND, "image id not found".to_string()))
-            }
-        }
+        Ok(status_response(&state.config, &id, status)?.map_completed(Json))
     })
     .await
     .map_err(|e| {
@@ -74,10 +65,87 @@ pub async fn image_status(
     })?
 }
 
+/// Fills in the place of a queued job, which only the queue knows
+pub fn with_queue_position(
+    state: &AppData,
+    id: &Uuid,
+    status: ImageStatus<CompletedImageGen>,
+) -> Result<ImageStatus<CompletedImageGen>, anyhow::Error> {
+    if let ImageStatus::Queued(_) = status {
+        if let Some(position) = state.queue.position(state.jobs.as_ref(), id)? {
+            return Ok(ImageStatus::Queued(position));
+        }
+    }
+    Ok(status)
+}
+
+/// Status as returned to clients, with the download links of a completed image
+pub fn status_response(
+    config: &AppConfig,
+    id: &Uuid,
+    status: ImageStatus<CompletedImageGen>,
+) -> Result<ImageStatus<CompletedImageResponse>, (StatusCode, String)> {
+    status
+        .map_completed(|c| {
+            c.map(|c| CompletedImageResponse {
+                download_url: format!("{}/{}/download", config.root_url(), id),
+                aovs_url: c
+                    .aovs
+                    .as_ref()
+                    .map(|_| format!("{}/{}/aovs", config.root_url(), id)),
+                stats: c.stats.clone(),
+            })
+            .map_err(|e| anyhow_error_http_response(&e))
+        })
+        .transpose_complete()
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct CompletedImageResponse {
     // #[schema(example = "https://raytracing-iow.shuttle.rs/:id/download")]
     download_url: String,
+    /// Multi-layer EXR of the render passes when any were requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aovs_url: Option<String>,
+    /// Missing for images rendered before statistics were kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<RenderStatistics>,
+}
+
+/// How the image was rendered, resubmit with the seed to render it again
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct RenderStatistics {
+    /// Seconds spent rendering
+    pub time: f64,
+    /// Rays cast, one per bounce
+    pub rays: u64,
+    pub rays_per_second: f64,
+    /// Surfaces hit per camera ray on average
+    pub average_depth: f64,
+    pub samples_per_pixel: f64,
+    pub seed: u64,
+}
+
+impl From<RenderStats> for RenderStatistics {
+    fn from(value: RenderStats) -> Self {
+        Self {
+            time: value.time.as_secs_f64(),
+            rays: value.rays,
+            rays_per_second: value.rays_per_second,
+            average_depth: value.average_depth,
+            samples_per_pixel: value.samples_per_pixel,
+            seed: value.seed,
+        }
+    }
+}
+
+/// Place of a job waiting for a render worker
+#[derive(Clone, Default, Serialize, ToSchema)]
+pub struct QueuePosition {
+    /// Jobs ahead in the queue plus one
+    pub position: usize,
+    /// Estimated from the running jobs and the average render time, unknown until a render finished
+    pub estimated_start: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Serialize, ToSchema)]
@@ -115,25 +183,20 @@ fn human_readable<S: Serializer>(dur: &Duration, serializer: S) -> Result<S::Ok,
 
 #[derive(Clone, Serialize)]
 pub enum ImageStatus<C> {
-    Queued,
+    Queued(QueuePosition),
     Rendering(Rendering),
     Completed(C),
+    /// Stopped over the API before it completed
+    Cancelled,
 }
 
 impl<C> ImageStatus<C> {
-    pub fn as_ref(&self) -> ImageStatus<&C> {
-        match *self {
-            ImageStatus::Queued => ImageStatus::Queued,
-            ImageStatus::Rendering(ref u) => ImageStatus::Rendering(u.clone()),
-            ImageStatus::Completed(ref c) => ImageStatus::Completed(c),
-        }
-    }
-
     pub fn map_completed<O, F: FnOnce(C) -> O>(self, op: F) -> ImageStatus<O> {
         match self {
-            ImageStatus::Queued => ImageStatus::Queued,
+            ImageStatus::Queued(q) => ImageStatus::Queued(q),
             ImageStatus::Rendering(u) => ImageStatus::Rendering(u),
             ImageStatus::Completed(c) => ImageStatus::Completed(op(c)),
+            ImageStatus::Cancelled => ImageStatus::Cancelled,
         }
     }
 }
@@ -141,9 +204,22 @@ impl<C> ImageStatus<C> {
 impl<T, E> ImageStatus<Result<T, E>> {
     pub fn transpose_complete(self) -> Result<ImageStatus<T>, E> {
         match self {
-            ImageStatus::Queued => Ok(ImageStatus::Queued),
+            ImageStatus::Queued(q) => Ok(ImageStatus::Queued(q)),
             ImageStatus::Rendering(u) => Ok(ImageStatus::Rendering(u)),
             ImageStatus::Completed(c) => c.map(|t| ImageStatus::Completed(t)),
+            ImageStatus::Cancelled => Ok(ImageStatus::Cancelled),
+        }
+    }
+}
+
+/// Serializes a status as the body of `GET /{id}`, a completed image without the variant name
+pub struct StatusBody<'a, C>(pub &'a ImageStatus<C>);
+
+impl<C: Serialize> Serialize for StatusBody<'_, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            ImageStatus::Completed(c) => c.serialize(serializer),
+            status => status.serialize(serializer),
         }
     }
 }
@@ -151,11 +227,14 @@ impl<T, E> ImageStatus<Result<T, E>> {
 impl<C: IntoResponse> IntoResponse for ImageStatus<C> {
     fn into_response(self) -> axum::response::Response {
         match self {
-            Self::Queued => (StatusCode::ACCEPTED, Json(ImageStatus::<()>::Queued)).into_response(),
+            Self::Queued(q) => {
+                (StatusCode::ACCEPTED, Json(ImageStatus::<()>::Queued(q))).into_response()
+            }
             Self::Rendering(u) => {
                 (StatusCode::ACCEPTED, Json(ImageStatus::<()>::Rendering(u))).into_response()
             }
             Self::Completed(c) => c.into_response(),
+            Self::Cancelled => (StatusCode::OK, Json(ImageStatus::<()>::Cancelled)).into_response(),
         }
     }
 }
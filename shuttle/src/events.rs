@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    endpoints::{events::PreviewFrame, status::ImageStatus},
+    state::CompletedImageGen,
+    store::{Job, JobStore},
+};
+
+/// Events kept for subscribers that fall behind
+const CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub enum JobEvent {
+    Status(ImageStatus<CompletedImageGen>),
+    Preview(Arc<PreviewFrame>),
+    /// The queue moved, sent with the id of the job that joined or left it. Every queued job
+    /// may have a new position.
+    QueueMoved,
+}
+
+/// Broadcasts job events to every subscriber, subscribers filter by the job id
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<(Uuid, JobEvent)>);
+
+impl Events {
+    pub fn new() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+
+    pub fn send(&self, id: Uuid, event: JobEvent) {
+        // fails only when nobody is listening
+        let _ = self.0.send((id, event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(Uuid, JobEvent)> {
+        self.0.subscribe()
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+}
+
+/// Job store that sends every status change as an event
+pub struct NotifyingStore {
+    inner: Box<dyn JobStore>,
+    events: Events,
+}
+
+impl NotifyingStore {
+    pub fn new(inner: Box<dyn JobStore>, events: Events) -> Self {
+        Self { inner, events }
+    }
+}
+
+impl JobStore for NotifyingStore {
+    fn insert(&self, id: Uuid, job: Job) -> Result<(), anyhow::Error> {
+        let status = job.status.clone();
+        self.inner.insert(id, job)?;
+        self.events.send(id, JobEvent::Status(status));
+        Ok(())
+    }
+
+    fn get(&self, id: &Uuid) -> Result<Option<Job>, anyhow::Error> {
+        self.inner.get(id)
+    }
+
+    fn set_status(
+        &self,
+        id: &Uuid,
+        status: ImageStatus<CompletedImageGen>,
+    ) -> Result<(), anyhow::Error> {
+        self.inner.set_status(id, status.clone())?;
+        self.events.send(*id, JobEvent::Status(status));
+        Ok(())
+    }
+
+    fn unfinished(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        self.inner.unfinished()
+    }
+
+    fn list(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        self.inner.list()
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize, anyhow::Error> {
+        self.inner.purge(before)
+    }
+}
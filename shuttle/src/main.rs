@@ -1,30 +1,57 @@
+mod access;
 mod config;
 mod endpoints;
+mod events;
 mod models;
 mod openapi;
+mod queue;
 mod render;
 mod state;
+mod store;
 mod utils;
 
+use std::{thread, time::Duration};
+
 use axum::{
     response::Redirect,
     routing::{get, post},
     Router,
 };
 use config::AppConfig;
-use endpoints::{download::download_image, gen::gen_image, status::image_status};
+use endpoints::{
+    cancel::cancel_image,
+    download::{download_aovs, download_image},
+    events::image_events,
+    gen::{gen_image, resume_jobs},
+    jobs::{list_jobs, resubmit_job},
+    status::image_status,
+};
 use envconfig::Envconfig;
 use openapi::ApiDoc;
+use queue::start_workers;
 use state::AppState;
 use tower_http::services::ServeDir;
-use tracing::info;
+use tracing::{error, info};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+/// How often finished jobs past their retention are removed
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 #[shuttle_runtime::main]
 async fn main() -> shuttle_axum::ShuttleAxum {
     let config = AppConfig::init_from_env().map_err(anyhow::Error::from)?;
-    let state = AppState::new(config.clone());
+    let state = AppState::new(config.clone())?;
+    resume_jobs(&state)?;
+    start_workers(&state)?;
+
+    let purge_state = state.clone();
+    thread::spawn(move || loop {
+        if let Err(e) = purge_state.purge_expired() {
+            error!(message = "Failed to purge expired jobs", err = ?e);
+        }
+        thread::sleep(PURGE_INTERVAL);
+    });
 
     info!(message = "Starting Raytracing In One Weekend", config = ?config);
     let router = Router::new()
@@ -32,8 +59,12 @@ async fn main() -> shuttle_axum::ShuttleAxum {
         .nest_service("/ui", ServeDir::new("shuttle/assets"))
         .route("/", get(|| async { Redirect::to("/ui") }))
         .route("/", post(gen_image))
-        .route("/:id", get(image_status))
+        .route("/jobs", get(list_jobs))
+        .route("/:id", get(image_status).delete(cancel_image))
         .route("/:id/download", get(download_image))
+        .route("/:id/aovs", get(download_aovs))
+        .route("/:id/events", get(image_events))
+        .route("/:id/resubmit", post(resubmit_job))
         .with_state(state);
 
     Ok(router.into())
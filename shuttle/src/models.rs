@@ -1,71 +1,418 @@
+use anyhow::anyhow;
+use rand::{rngs::SmallRng, SeedableRng};
 use raytracing_iow::{
-    materials::{dielectric::Dielectric, lambertian::Lambertain, metal::Metal},
+    materials::{
+        bump::{Bump, Bumped},
+        coated::Coated,
+        dielectric::Dielectric,
+        henyey_greenstein::HenyeyGreenstein,
+        isotropic::Isotropic,
+        lambertian::Lambertain,
+        metal::Metal,
+        mix::Mix,
+        principled::Principled,
+    },
+    noise::Perlin,
+    shapes::{
+        constant_medium::ConstantMedium,
+        cube::Cube as CoreCube,
+        heterogeneous_medium::{Density, HeterogeneousMedium},
+    },
     vec3::Vec3,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+/// Delta tracking takes a step per unit of density along a ray through a heterogeneous medium
+const MAX_MEDIUM_DENSITY: f64 = 1000.;
+/// Turbulence octaves summed for every density lookup of a heterogeneous medium
+const MAX_MEDIUM_OCTAVES: u32 = 16;
+
 #[derive(Deserialize, ToSchema)]
 pub struct Object {
     pub shape: Shape,
     pub material: Material,
+    /// Cut out or fade parts of the surface, 1 is opaque and 0 is fully transparent
+    #[serde(default)]
+    pub opacity: Option<Opacity>,
+    /// How the inside of the surface is shaded, defaults to the same material as the outside
+    #[serde(default)]
+    pub back_face: Option<BackFace>,
+}
+
+impl From<Object> for raytracing_iow::world::Object {
+    fn from(value: Object) -> Self {
+        let mut object = raytracing_iow::world::Object::new(value.shape, value.material);
+        if let Some(opacity) = value.opacity {
+            object = object.with_opacity(opacity.texture, opacity.mode.into());
+        }
+        if let Some(back_face) = value.back_face {
+            object = object.with_back_face(back_face.into());
+        }
+        object
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct Opacity {
+    pub texture: Texture,
+    pub mode: AlphaMode,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub enum AlphaMode {
+    /// Soft edges, rays pass through in proportion to the transparency
+    Stochastic,
+    /// Solid where the opacity is at least the cutoff, a hole everywhere else
+    Threshold(f64),
 }
 
-impl Into<raytracing_iow::world::Object> for Object {
-    fn into(self) -> raytracing_iow::world::Object {
-        raytracing_iow::world::Object::new(self.shape, self.material)
+impl From<AlphaMode> for raytracing_iow::world::AlphaMode {
+    fn from(value: AlphaMode) -> Self {
+        match value {
+            AlphaMode::Stochastic => raytracing_iow::world::AlphaMode::Stochastic,
+            AlphaMode::Threshold(cutoff) => raytracing_iow::world::AlphaMode::Threshold(cutoff),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub enum BackFace {
+    Shade,
+    /// Rays pass through the back of the surface
+    Invisible,
+    Material(Material),
+}
+
+impl From<BackFace> for raytracing_iow::world::BackFace {
+    fn from(value: BackFace) -> Self {
+        match value {
+            BackFace::Shade => raytracing_iow::world::BackFace::Shade,
+            BackFace::Invisible => raytracing_iow::world::BackFace::Invisible,
+            BackFace::Material(m) => raytracing_iow::world::BackFace::Material(m.into()),
+        }
     }
 }
 
 #[derive(Deserialize, ToSchema)]
 pub enum Shape {
     Sphere(Sphere),
+    Cube(Cube),
+    /// Smoke volume filling the boundary shape, use with the `Isotropic` material
+    ConstantMedium {
+        boundary: Box<Shape>,
+        density: f64,
+    },
+    /// Smoke volume with a noisy density of up to `density`, use with the `Isotropic` or
+    /// `HenyeyGreenstein` material
+    HeterogeneousMedium {
+        boundary: Box<Shape>,
+        density: f64,
+        frequency: f64,
+        octaves: u32,
+        #[serde(default)]
+        seed: u64,
+    },
 }
 
-impl Into<raytracing_iow::shapes::Shape> for Shape {
-    fn into(self) -> raytracing_iow::shapes::Shape {
+impl Shape {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
         match self {
+            Shape::Sphere(_) | Shape::Cube(_) => Ok(()),
+            Shape::ConstantMedium { boundary, density } => {
+                if !density.is_finite() || *density <= 0. {
+                    return Err(anyhow!(
+                        "Medium density: {} has to be finite and greater than 0",
+                        density
+                    ));
+                }
+                boundary.validate()
+            }
+            Shape::HeterogeneousMedium {
+                boundary,
+                density,
+                frequency,
+                octaves,
+                ..
+            } => {
+                if !density.is_finite() || *density <= 0. || *density > MAX_MEDIUM_DENSITY {
+                    return Err(anyhow!(
+                        "Medium density: {} has to be greater than 0 and at most {}",
+                        density,
+                        MAX_MEDIUM_DENSITY
+                    ));
+                }
+                if !frequency.is_finite() || *frequency <= 0. {
+                    return Err(anyhow!(
+                        "Medium frequency: {} has to be finite and greater than 0",
+                        frequency
+                    ));
+                }
+                if !(1..=MAX_MEDIUM_OCTAVES).contains(octaves) {
+                    return Err(anyhow!(
+                        "Medium octaves: {} has to be between 1 and {}",
+                        octaves,
+                        MAX_MEDIUM_OCTAVES
+                    ));
+                }
+                boundary.validate()
+            }
+        }
+    }
+}
+
+impl From<Shape> for raytracing_iow::shapes::Shape {
+    fn from(value: Shape) -> Self {
+        match value {
             Shape::Sphere(s) => raytracing_iow::shapes::Shape::Sphere(s.into()),
+            Shape::Cube(c) => raytracing_iow::shapes::Shape::Cube(c.into()),
+            Shape::ConstantMedium { boundary, density } => {
+                raytracing_iow::shapes::Shape::ConstantMedium(ConstantMedium::new(
+                    *boundary, density,
+                ))
+            }
+            Shape::HeterogeneousMedium {
+                boundary,
+                density,
+                frequency,
+                octaves,
+                seed,
+            } => {
+                let mut rng = SmallRng::seed_from_u64(seed);
+                raytracing_iow::shapes::Shape::HeterogeneousMedium(HeterogeneousMedium::new(
+                    *boundary,
+                    Density::noise(&mut rng, density, frequency, octaves),
+                ))
+            }
         }
     }
 }
 
 #[derive(Deserialize, ToSchema)]
 pub enum Sphere {
-    Stationary { center: Vec3, radius: f64 },
+    Stationary {
+        center: Vec3,
+        radius: f64,
+    },
+    /// Moves from `from` to `to` while the shutter is open, giving motion blur
+    Moving {
+        from: Vec3,
+        to: Vec3,
+        radius: f64,
+    },
 }
 
-impl Into<raytracing_iow::shapes::sphere::Sphere> for Sphere {
-    fn into(self) -> raytracing_iow::shapes::sphere::Sphere {
-        match self {
+impl From<Sphere> for raytracing_iow::shapes::sphere::Sphere {
+    fn from(value: Sphere) -> Self {
+        match value {
             Sphere::Stationary { center, radius } => {
                 raytracing_iow::shapes::sphere::Sphere::new(center, radius)
             }
+            Sphere::Moving { from, to, radius } => {
+                raytracing_iow::shapes::sphere::Sphere::new_moving(from, to, radius)
+            }
+        }
+    }
+}
+
+/// Axis aligned box
+#[derive(Deserialize, ToSchema)]
+pub enum Cube {
+    MinMax { min: Vec3, max: Vec3 },
+    Center { center: Vec3, size: f64 },
+}
+
+impl From<Cube> for CoreCube {
+    fn from(value: Cube) -> Self {
+        match value {
+            Cube::MinMax { min, max } => CoreCube::new_min_max(min, max),
+            Cube::Center { center, size } => CoreCube::new_center(center, size),
         }
     }
 }
 
 #[derive(Deserialize, ToSchema)]
 pub enum Material {
-    Lambertain { color: Color },
-    Metal { color: Color, fuzziness: f64 },
-    Dielectric { index_of_refraction: f64 },
+    Lambertain {
+        color: Color,
+    },
+    Metal {
+        color: Color,
+        fuzziness: f64,
+    },
+    /// Glass like material, `absorption` tints light travelling through it and `dispersion`
+    /// is the Cauchy B coefficient that splits colors in spectral renders
+    Dielectric {
+        index_of_refraction: f64,
+        #[serde(default)]
+        absorption: Option<Color>,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        dispersion: f64,
+    },
+    Isotropic {
+        color: Color,
+    },
+    /// Scattering for volumes, `g` above 0 scatters forward and below 0 backward
+    HenyeyGreenstein {
+        color: Color,
+        g: f64,
+    },
+    /// Physically based material covering plastics through metals
+    Principled {
+        base_color: Color,
+        roughness: f64,
+        metallic: f64,
+        #[serde(default)]
+        specular: Option<f64>,
+        #[serde(default)]
+        clearcoat: Option<Clearcoat>,
+    },
+    /// Blend of two materials, a weight of 0 is only `a` and 1 is only `b`
+    Mix {
+        a: Box<Material>,
+        b: Box<Material>,
+        weight: Texture,
+    },
+    /// Dielectric coating over a base material
+    Coated {
+        base: Box<Material>,
+        index_of_refraction: f64,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        tint: Option<Color>,
+    },
+    /// Roughens the shading of a material with a noise height field
+    Bumped {
+        material: Box<Material>,
+        frequency: f64,
+        strength: f64,
+        #[serde(default)]
+        seed: u64,
+    },
 }
 
-impl Into<raytracing_iow::materials::Material> for Material {
-    fn into(self) -> raytracing_iow::materials::Material {
-        match self {
-            Material::Lambertain { color } => raytracing_iow::materials::Material::Lambertain(
-                Lambertain::new((color.r, color.g, color.b).into()),
-            ),
-            Material::Metal { color, fuzziness } => raytracing_iow::materials::Material::Metal(
-                Metal::new((color.r, color.g, color.b).into(), fuzziness),
-            ),
+impl From<Material> for raytracing_iow::materials::Material {
+    fn from(value: Material) -> Self {
+        match value {
+            Material::Lambertain { color } => {
+                raytracing_iow::materials::Material::Lambertain(Lambertain::new(color.into()))
+            }
+            Material::Metal { color, fuzziness } => {
+                raytracing_iow::materials::Material::Metal(Metal::new(color.into(), fuzziness))
+            }
             Material::Dielectric {
                 index_of_refraction,
-            } => raytracing_iow::materials::Material::Dielectric(Dielectric::new(
+                absorption,
+                roughness,
+                dispersion,
+            } => {
+                let mut dielectric = Dielectric::new(index_of_refraction)
+                    .with_roughness(roughness)
+                    .with_dispersion(dispersion);
+                if let Some(absorption) = absorption {
+                    dielectric = dielectric.with_absorption(absorption.into());
+                }
+                raytracing_iow::materials::Material::Dielectric(dielectric)
+            }
+            Material::Isotropic { color } => {
+                raytracing_iow::materials::Material::Isotropic(Isotropic::new(color.into()))
+            }
+            Material::HenyeyGreenstein { color, g } => {
+                HenyeyGreenstein::new(color.into(), g).into()
+            }
+            Material::Principled {
+                base_color,
+                roughness,
+                metallic,
+                specular,
+                clearcoat,
+            } => {
+                let mut principled = Principled::new(base_color.into(), roughness, metallic);
+                if let Some(specular) = specular {
+                    principled = principled.with_specular(specular);
+                }
+                if let Some(Clearcoat { amount, roughness }) = clearcoat {
+                    principled = principled.with_clearcoat(amount, roughness);
+                }
+                principled.into()
+            }
+            Material::Mix { a, b, weight } => Mix::new(*a, *b, weight).into(),
+            Material::Coated {
+                base,
                 index_of_refraction,
-            )),
+                roughness,
+                tint,
+            } => {
+                let mut coated = Coated::new(*base, index_of_refraction).with_roughness(roughness);
+                if let Some(tint) = tint {
+                    coated = coated.with_tint(tint.into());
+                }
+                coated.into()
+            }
+            Material::Bumped {
+                material,
+                frequency,
+                strength,
+                seed,
+            } => {
+                let mut rng = SmallRng::seed_from_u64(seed);
+                Bumped::new(
+                    *material,
+                    Bump::Noise {
+                        perlin: Perlin::new(&mut rng),
+                        frequency,
+                        strength,
+                    },
+                )
+                .into()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct Clearcoat {
+    pub amount: f64,
+    pub roughness: f64,
+}
+
+/// Value that is either a constant number or varies over the surface
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Texture {
+    Constant(f64),
+    Pattern(Pattern),
+}
+
+#[derive(Deserialize, ToSchema)]
+pub enum Pattern {
+    /// Alternating squares in uv space, `scale` squares per unit
+    Checker { scale: f64, even: f64, odd: f64 },
+    /// Turbulence in the range of 0 to 1
+    Noise {
+        frequency: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+impl From<Texture> for raytracing_iow::texture::Texture {
+    fn from(value: Texture) -> Self {
+        match value {
+            Texture::Constant(v) => raytracing_iow::texture::Texture::Constant(v),
+            Texture::Pattern(Pattern::Checker { scale, even, odd }) => {
+                raytracing_iow::texture::Texture::Checker { scale, even, odd }
+            }
+            Texture::Pattern(Pattern::Noise { frequency, seed }) => {
+                let mut rng = SmallRng::seed_from_u64(seed);
+                raytracing_iow::texture::Texture::Noise {
+                    perlin: Perlin::new(&mut rng),
+                    frequency,
+                }
+            }
         }
     }
 }
@@ -76,3 +423,107 @@ pub struct Color {
     pub g: f64,
     pub b: f64,
 }
+
+impl From<Color> for raytracing_iow::color::Color {
+    fn from(value: Color) -> Self {
+        raytracing_iow::color::Color::new(value.r, value.g, value.b)
+    }
+}
+
+/// Atmosphere filling the whole scene
+#[derive(Deserialize, ToSchema)]
+pub struct Fog {
+    pub density: f64,
+    pub color: Color,
+}
+
+impl Fog {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if !self.density.is_finite() || self.density <= 0. {
+            return Err(anyhow!(
+                "Fog density: {} has to be finite and greater than 0",
+                self.density
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<Fog> for raytracing_iow::world::Fog {
+    fn from(value: Fog) -> Self {
+        raytracing_iow::world::Fog::new(value.density, value.color.into())
+    }
+}
+
+/// Light coming from outside the scene
+#[derive(Deserialize, ToSchema)]
+pub enum Background {
+    /// White at the horizon to sky blue straight up
+    Sky,
+    Solid(Color),
+    /// Blends from `bottom` straight down to `top` straight up
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+}
+
+impl From<Background> for raytracing_iow::world::Background {
+    fn from(value: Background) -> Self {
+        match value {
+            Background::Sky => raytracing_iow::world::Background::Sky,
+            Background::Solid(color) => raytracing_iow::world::Background::Solid(color.into()),
+            Background::Gradient { bottom, top } => raytracing_iow::world::Background::Gradient {
+                bottom: bottom.into(),
+                top: top.into(),
+            },
+        }
+    }
+}
+
+/// How the linear render is turned into 8 or 16 bit pixels
+#[derive(Deserialize, Serialize, ToSchema, Clone)]
+pub struct Output {
+    /// Gamma the image is encoded with, 1 writes the linear values as is
+    #[serde(default = "default_gamma")]
+    #[schema(default = 1.0, example = 2.2)]
+    pub gamma: f64,
+    #[serde(default)]
+    pub tone_map: ToneMap,
+}
+
+impl Output {
+    pub fn apply(&self, color: raytracing_iow::color::Color) -> raytracing_iow::color::Color {
+        let [r, g, b] = color.into_arr().map(|c| {
+            let c = match self.tone_map {
+                ToneMap::Clamp => c,
+                ToneMap::Reinhard => c / (1. + c),
+            };
+            c.max(0.).powf(1. / self.gamma)
+        });
+        raytracing_iow::color::Color::new(r, g, b)
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            gamma: default_gamma(),
+            tone_map: ToneMap::default(),
+        }
+    }
+}
+
+fn default_gamma() -> f64 {
+    1.
+}
+
+/// How values brighter than white are brought into range
+#[derive(Deserialize, Serialize, ToSchema, Default, Clone, Copy)]
+pub enum ToneMap {
+    /// Clips anything brighter than white
+    #[default]
+    Clamp,
+    /// Smoothly compresses highlights
+    Reinhard,
+}
@@ -1,16 +1,35 @@
-use raytracing_iow::{render::camera::CameraConfig, vec3::Vec3};
+use raytracing_iow::{
+    render::{
+        aov::Aov,
+        camera::CameraConfig,
+        filter::Filter,
+        lens::{Focus, Lens},
+        post::{ColorGrade, Effect, Lut},
+        viewport::{FisheyeMapping, ViewportConfig},
+    },
+    vec3::Vec3,
+};
 use utoipa::{
-    openapi::{ArrayBuilder, ObjectBuilder, OneOfBuilder, Ref, RefOr, Schema, SchemaType},
-    OpenApi, ToSchema,
+    openapi::{
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        ArrayBuilder, ObjectBuilder, OneOfBuilder, Ref, RefOr, Schema, SchemaType,
+    },
+    Modify, OpenApi, ToSchema,
 };
 
 use crate::{
     endpoints::{
         self,
+        download::OutputFormat,
+        events::PreviewFrame,
         gen::{GenImageRequest, GenImageResponse},
-        status::{CompletedImageResponse, ImageStatus},
+        jobs::{JobList, JobState, JobSummary},
+        status::{CompletedImageResponse, ImageStatus, QueuePosition, RenderStatistics},
+    },
+    models::{
+        AlphaMode, BackFace, Background, Clearcoat, Color, Cube, Fog, Material, Object, Opacity,
+        Output, Pattern, Shape, Sphere, Texture, ToneMap,
     },
-    models::{Color, Material, Object, Shape, Sphere},
 };
 
 pub type ImageStatusResponse = ImageStatus<CompletedImageResponse>;
@@ -28,7 +47,8 @@ impl ToSchema<'static> for ImageStatusResponse {
                     .item(
                         ObjectBuilder::new()
                             .title(Some("Queued"))
-                            .schema_type(SchemaType::String)
+                            .property("Queued", Ref::from_schema_name("QueuePosition"))
+                            .required("Queued")
                             .build(),
                     )
                     .item(
@@ -42,31 +62,91 @@ impl ToSchema<'static> for ImageStatusResponse {
                             .build(),
                     )
                     .item(Ref::from_schema_name("CompletedImageResponse"))
-                    .example(Some("Queued".into()))
+                    .item(
+                        ObjectBuilder::new()
+                            .title(Some("Cancelled"))
+                            .schema_type(SchemaType::String)
+                            .enum_values(Some(["Cancelled"]))
+                            .build(),
+                    )
+                    .example(Some(serde_json::json!({
+                        "Queued": { "position": 2, "estimated_start": "2023-10-01T12:00:00Z" }
+                    })))
                     .build(),
             )),
         )
     }
 }
 
+/// API keys are sent in the `X-Api-Key` header or as a bearer token
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+            );
+            components.add_security_scheme(
+                "api_key_query",
+                SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("api_key"))),
+            );
+        }
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
+    modifiers(&SecurityAddon),
     paths(
         endpoints::gen::gen_image,
         endpoints::status::image_status,
-        endpoints::download::download_image
+        endpoints::cancel::cancel_image,
+        endpoints::download::download_image,
+        endpoints::download::download_aovs,
+        endpoints::events::image_events,
+        endpoints::jobs::list_jobs,
+        endpoints::jobs::resubmit_job
     ),
     components(schemas(
         GenImageRequest,
         GenImageResponse,
         CompletedImageResponse,
+        RenderStatistics,
         Color,
+        Fog,
         Sphere,
+        Cube,
         Shape,
         Material,
+        Clearcoat,
+        Texture,
+        Pattern,
         Object,
+        Opacity,
+        AlphaMode,
+        BackFace,
+        Background,
+        Output,
+        ToneMap,
         Vec3,
         CameraConfig,
+        Lens,
+        Focus,
+        Filter,
+        Aov,
+        Effect,
+        ColorGrade,
+        Lut,
+        ViewportConfig,
+        FisheyeMapping,
+        QueuePosition,
+        PreviewFrame,
+        OutputFormat,
+        JobList,
+        JobSummary,
+        JobState,
         ImageStatusResponse
     ))
 )]
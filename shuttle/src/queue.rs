@@ -0,0 +1,289 @@
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use rayon::ThreadPoolBuilder;
+use raytracing_iow::render::cancel::CancelToken;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    endpoints::{
+        gen::GenImageRequest,
+        status::{ImageStatus, QueuePosition},
+    },
+    events::{Events, JobEvent},
+    render::render_img,
+    state::AppData,
+    store::{Job, JobStore},
+    utils::map_poison_error,
+};
+
+/// Weight of the latest render in the average render time
+const AVERAGE_WEIGHT: f64 = 0.3;
+/// Pause of a worker after an error, so a broken queue isn't retried in a busy loop
+const ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// What cancelling a job did
+pub enum Cancelled {
+    /// Removed from the queue before it started, `cost` is what the job was charged
+    Dequeued { cost: u64 },
+    /// The render stops after the in-flight tiles finish
+    Stopping,
+    /// The job isn't queued or rendering
+    NotActive,
+}
+
+/// First in first out queue of jobs waiting for a render worker.
+/// Positions are computed when asked for instead of being stored with every job.
+pub struct RenderQueue {
+    pending: Mutex<VecDeque<(Uuid, GenImageRequest)>>,
+    ready: Condvar,
+    /// Cancel flags of the jobs being rendered
+    running: Mutex<HashMap<Uuid, CancelToken>>,
+    /// Smoothed time of the completed renders
+    average: Mutex<Option<Duration>>,
+    workers: usize,
+    max_queued: usize,
+    /// Told whenever the positions or estimates of the queued jobs change
+    events: Events,
+}
+
+impl RenderQueue {
+    pub fn new(workers: usize, max_queued: usize, events: Events) -> Self {
+        Self {
+            pending: Default::default(),
+            ready: Condvar::new(),
+            running: Default::default(),
+            average: Default::default(),
+            workers: workers.max(1),
+            max_queued,
+            events,
+        }
+    }
+
+    /// Stores the job and adds it to the back of the queue, returns false when the queue is full
+    pub fn push(
+        &self,
+        jobs: &dyn JobStore,
+        id: Uuid,
+        job: Job,
+        req: GenImageRequest,
+    ) -> Result<bool, anyhow::Error> {
+        let mut pending = self.pending.lock().map_err(map_poison_error)?;
+        if pending.len() >= self.max_queued {
+            return Ok(false);
+        }
+        jobs.insert(id, job)?;
+        pending.push_back((id, req));
+        self.ready.notify_one();
+        Ok(true)
+    }
+
+    /// Queues a job that is already stored, ignoring the queue limit
+    pub fn resume(&self, id: Uuid, req: GenImageRequest) -> Result<(), anyhow::Error> {
+        let mut pending = self.pending.lock().map_err(map_poison_error)?;
+        pending.push_back((id, req));
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// Removes a queued job or stops a running one
+    pub fn cancel(&self, jobs: &dyn JobStore, id: &Uuid) -> Result<Cancelled, anyhow::Error> {
+        let mut pending = self.pending.lock().map_err(map_poison_error)?;
+        if let Some((_, req)) = pending
+            .iter()
+            .position(|(queued, _)| queued == id)
+            .and_then(|i| pending.remove(i))
+        {
+            jobs.set_status(id, ImageStatus::Cancelled)?;
+            self.events.send(*id, JobEvent::QueueMoved);
+            return Ok(Cancelled::Dequeued { cost: req.cost() });
+        }
+        drop(pending);
+
+        match self.running.lock().map_err(map_poison_error)?.get(id) {
+            Some(cancel) => {
+                cancel.cancel();
+                Ok(Cancelled::Stopping)
+            }
+            None => Ok(Cancelled::NotActive),
+        }
+    }
+
+    /// Place of a queued job, `None` once it left the queue
+    pub fn position(
+        &self,
+        jobs: &dyn JobStore,
+        id: &Uuid,
+    ) -> Result<Option<QueuePosition>, anyhow::Error> {
+        let Some(index) = self
+            .pending
+            .lock()
+            .map_err(map_poison_error)?
+            .iter()
+            .position(|(queued, _)| queued == id)
+        else {
+            return Ok(None);
+        };
+        let average = *self.average.lock().map_err(map_poison_error)?;
+        let running: Vec<Uuid> = self
+            .running
+            .lock()
+            .map_err(map_poison_error)?
+            .keys()
+            .copied()
+            .collect();
+
+        // time until each worker is free, unknown once it depends on an unknown render time
+        let mut free = Vec::with_capacity(self.workers);
+        for running in &running {
+            free.push(match jobs.get(running)?.map(|job| job.status) {
+                Some(ImageStatus::Rendering(r)) => Some(r.eta),
+                // just taken from the queue
+                _ => average,
+            });
+        }
+        free.resize(self.workers, Some(Duration::ZERO));
+
+        let mut start = None;
+        for _ in 0..=index {
+            let (slot, next) = free
+                .iter()
+                .copied()
+                .enumerate()
+                .min_by_key(|(_, start)| start.unwrap_or(Duration::MAX))
+                .unwrap_or((0, None));
+            free[slot] = next.zip(average).map(|(next, avg)| next + avg);
+            start = next;
+        }
+
+        let estimated_start = start
+            .and_then(|start| chrono::Duration::from_std(start).ok())
+            .map(|start| Utc::now() + start);
+        Ok(Some(QueuePosition {
+            position: index + 1,
+            estimated_start,
+        }))
+    }
+
+    /// Waits for the next job
+    fn pop(&self) -> Result<(Uuid, GenImageRequest, CancelToken), anyhow::Error> {
+        let mut pending = self.pending.lock().map_err(map_poison_error)?;
+        loop {
+            if let Some((id, req)) = pending.pop_front() {
+                let cancel = CancelToken::new();
+                self.running
+                    .lock()
+                    .map_err(map_poison_error)?
+                    .insert(id, cancel.clone());
+                self.events.send(id, JobEvent::QueueMoved);
+                return Ok((id, req, cancel));
+            }
+            pending = self.ready.wait(pending).map_err(map_poison_error)?;
+        }
+    }
+
+    /// `took` is only given for completed renders so failed and cancelled ones don't skew the
+    /// average
+    fn finished(&self, id: &Uuid, took: Option<Duration>) -> Result<(), anyhow::Error> {
+        self.running.lock().map_err(map_poison_error)?.remove(id);
+        if let Some(took) = took {
+            let mut average = self.average.lock().map_err(map_poison_error)?;
+            *average = Some(match *average {
+                Some(avg) => avg.mul_f64(1. - AVERAGE_WEIGHT) + took.mul_f64(AVERAGE_WEIGHT),
+                None => took,
+            });
+        }
+        self.events.send(*id, JobEvent::QueueMoved);
+        Ok(())
+    }
+}
+
+/// Starts the render workers, each renders one job at a time with its own thread pool
+pub fn start_workers(state: &AppData) -> Result<(), anyhow::Error> {
+    let threads = state.config.threads_per_job();
+    info!(
+        message = "Starting render workers",
+        workers = state.queue.workers,
+        threads
+    );
+    for worker in 0..state.queue.workers {
+        let state = state.clone();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(move |i| format!("render-{}-{}", worker, i))
+            .build()?;
+        thread::spawn(move || loop {
+            if let Err(e) = pool.install(|| run_next(&state)) {
+                error!(message = "Render worker failed", worker, err = ?e);
+                thread::sleep(ERROR_BACKOFF);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn run_next(state: &AppData) -> Result<(), anyhow::Error> {
+    let (id, req, cancel) = state.queue.pop()?;
+    info!(message = "Generating Image", id = %id);
+    let cost = req.cost();
+    let mut running = Running {
+        queue: &state.queue,
+        id,
+        took: None,
+    };
+
+    let start = Instant::now();
+    let rendered = panic::catch_unwind(AssertUnwindSafe(|| render_img(state, &id, req, &cancel)))
+        .unwrap_or_else(|panic| Err(anyhow!("Render panicked: {}", panic_message(&*panic))));
+    match rendered {
+        Ok(()) if !cancel.is_cancelled() => {
+            running.took = Some(start.elapsed());
+            return Ok(());
+        }
+        Ok(()) => {}
+        Err(e) => {
+            error!(message = "Error when processing image", err = ?e);
+            state
+                .jobs
+                .set_status(&id, ImageStatus::Completed(Err(Arc::new(e))))?;
+        }
+    }
+    // failed and cancelled renders don't count against the quota
+    if let Some(job) = state.jobs.get(&id)? {
+        state.access.refund_job(&job, cost);
+    }
+    Ok(())
+}
+
+/// Takes a job off the running ones however its render ended
+struct Running<'a> {
+    queue: &'a RenderQueue,
+    id: Uuid,
+    /// Only set for completed renders
+    took: Option<Duration>,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.queue.finished(&self.id, self.took) {
+            error!(message = "Unable to finish job", id = %self.id, err = ?e);
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
@@ -1,121 +1,278 @@
-use std::time::Duration;
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use chrono::Utc;
-use image::{ImageBuffer, Rgb, RgbImage};
+use image::{ImageBuffer, ImageFormat, Pixel, Primitive, Rgb, Rgb32FImage};
 use raytracing_iow::{
-    render::{camera::Camera, screen::Screen, viewport::ViewportConfig, PixelLocator},
+    color::{Color, BLACK},
+    render::{
+        aov::{Aov, AovImage},
+        camera::Camera,
+        cancel::CancelToken,
+        denoise::Denoiser,
+        film::Film,
+        post::post_process,
+        screen::Screen,
+        PixelLocator,
+    },
     world::{Object, World},
 };
+use tracing::warn;
 use uuid::Uuid;
 
 use crate::{
     endpoints::{
+        events::PreviewFrame,
         gen::GenImageRequest,
         status::{ImageStatus, Rendering},
     },
-    state::AppData,
-    utils::map_poison_error,
+    events::JobEvent,
+    models::Output,
+    state::{AppData, RenderedImage},
 };
 
-pub fn render_img(state: &AppData, id: &Uuid, req: GenImageRequest) -> Result<(), anyhow::Error> {
-    let mut img: RgbImage = ImageBuffer::new(req.width, req.height);
-
-    let progress = Progress::new(state, id);
-    progress.start(req.width * req.height)?;
+/// Renders the request and stores the image, marks the job cancelled when `cancel` is set
+pub fn render_img(
+    state: &AppData,
+    id: &Uuid,
+    mut req: GenImageRequest,
+    cancel: &CancelToken,
+) -> Result<(), anyhow::Error> {
+    // kept below 2^53 so the seed survives JSON numbers in browsers
+    req.camera_config
+        .seed
+        .get_or_insert_with(|| rand::random::<u64>() >> 11);
+    let passes = PREVIEW_PASSES.clamp(1, req.camera_config.samples_per_pixel.max(1));
+    let progress = Progress::start(state, id, req.width * req.height * passes)?;
 
     let screen = Screen::new(req.width.into(), req.height.into());
-    let viewport_config = ViewportConfig::Fov { vertical_fov: 20.0 };
-
-    let camera = Camera::new(req.camera_config, viewport_config);
+    let mut camera = Camera::new(req.camera_config, req.viewport);
+    if !req.aovs.is_empty() || req.denoise.is_some() {
+        camera = camera.with_aovs();
+    }
 
     let pixel_locator = PixelLocator::from_screen_and_camera(&screen, &camera);
-    let world: World = req
+    let mut world: World = req
         .objects
         .into_iter()
         .map(Into::into)
         .collect::<Vec<Object>>()
         .into();
+    if let Some(fog) = req.fog {
+        world = world.with_fog(fog.into());
+    }
+    if let Some(background) = req.background {
+        world = world.with_background(background.into());
+    }
 
-    for y in 0..req.height {
-        for x in 0..req.width {
-            let pixel_center = pixel_locator.pixel_center(x.into(), y.into());
-
-            let pixel = camera
-                .get_color(&world, &pixel_locator, pixel_center)
-                .into_arr();
-
-            img.put_pixel(
-                x,
-                y,
-                Rgb([
-                    (pixel[0] * 255.).trunc() as u8,
-                    (pixel[1] * 255.).trunc() as u8,
-                    (pixel[2] * 255.).trunc() as u8,
-                ]),
-            );
-            progress.inc()?;
-        }
+    let film = camera.render_progressive(
+        &world,
+        &pixel_locator,
+        &screen,
+        passes,
+        cancel,
+        |pixels| {
+            if let Err(e) = progress.inc(pixels as u32) {
+                warn!(message = "Unable to update progress", err = ?e);
+            }
+        },
+        |pass, film| {
+            // the last pass is sent as the completed image
+            if pass < passes && state.events.has_subscribers() {
+                match preview(film, &screen, &req.output, pass, passes) {
+                    Ok(frame) => state.events.send(*id, JobEvent::Preview(Arc::new(frame))),
+                    Err(e) => warn!(message = "Unable to create preview", err = ?e),
+                }
+            }
+        },
+    );
+    let Some(film) = film else {
+        return progress.cancelled();
+    };
+
+    let mut pixels = film.radiance();
+    let passes = film.aovs();
+    if let (Some(strength), Some(passes)) = (req.denoise, &passes) {
+        pixels = Denoiser::new(strength).denoise(&pixels, passes);
     }
+    let aovs = passes
+        .filter(|_| !req.aovs.is_empty())
+        .map(|passes| write_aovs(&passes, &pixels, &req.aovs))
+        .transpose()?;
+
+    let pixels = post_process(&req.post_processing, pixels, &screen, camera.seed());
+    let linear = Rgb32FImage::from_vec(
+        req.width,
+        req.height,
+        pixels
+            .into_iter()
+            .flat_map(|c| c.into_arr().map(|c| c as f32))
+            .collect(),
+    )
+    .ok_or(anyhow!("Rendered pixels don't match the image size"))?;
 
-    progress.complete(img)?;
+    progress.complete(RenderedImage {
+        linear,
+        output: req.output,
+        aovs,
+        stats: Some(camera.stats().into()),
+    })?;
 
     Ok(())
 }
 
+/// Applies the output transform, `channel` turns values from 0 to 1 into the pixel type
+pub fn to_image<P: Primitive>(
+    width: u32,
+    height: u32,
+    output: &Output,
+    pixels: impl IntoIterator<Item = Color>,
+    channel: impl Fn(f64) -> P,
+) -> ImageBuffer<Rgb<P>, Vec<P>>
+where
+    Rgb<P>: Pixel<Subpixel = P>,
+{
+    let mut img = ImageBuffer::new(width, height);
+    for (pixel, out) in pixels.into_iter().zip(img.pixels_mut()) {
+        let pixel = output.apply(pixel).clamp(0.0..0.9999).into_arr();
+        *out = Rgb(pixel.map(&channel));
+    }
+    img
+}
+
+/// Box filtered copy of the film so far that fits within `PREVIEW_SIZE`
+fn preview(
+    film: &Film,
+    screen: &Screen,
+    output: &Output,
+    pass: u32,
+    passes: u32,
+) -> Result<PreviewFrame, anyhow::Error> {
+    let (width, height) = (screen.width() as usize, screen.height() as usize);
+    let scale = width.max(height).div_ceil(PREVIEW_SIZE);
+    let (preview_width, preview_height) = (width.div_ceil(scale), height.div_ceil(scale));
+
+    let radiance = film.radiance();
+    let mut pixels = vec![(BLACK, 0.); preview_width * preview_height];
+    for (i, color) in radiance.into_iter().enumerate() {
+        let (sum, count) = &mut pixels[(i / width / scale) * preview_width + (i % width) / scale];
+        *sum += color;
+        *count += 1.;
+    }
+    let pixels = pixels.into_iter().map(|(sum, count)| (1. / count) * sum);
+
+    let image = to_image(
+        preview_width as u32,
+        preview_height as u32,
+        output,
+        pixels,
+        |c| (c * 255.) as u8,
+    );
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    Ok(PreviewFrame {
+        pass,
+        passes,
+        width: preview_width as u32,
+        height: preview_height as u32,
+        image: format!(
+            "data:image/png;base64,{}",
+            STANDARD.encode(png.into_inner())
+        ),
+    })
+}
+
+fn write_aovs(aovs: &AovImage, beauty: &[Color], passes: &[Aov]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buffer = Cursor::new(Vec::new());
+    aovs.write_exr(&mut buffer, Some(beauty), passes)?;
+    Ok(buffer.into_inner())
+}
+
+/// Passes the samples are split into, a preview is streamed after each one
+const PREVIEW_PASSES: u32 = 4;
+/// Largest width or height of a preview in pixels
+const PREVIEW_SIZE: usize = 160;
+/// How often rendering progress is written to the job store
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Counts rendered pixels from every render thread, the job store is only updated every
+/// `PROGRESS_INTERVAL` by whichever thread finishes a tile first
 struct Progress<'a> {
     state: &'a AppData,
     id: &'a Uuid,
+    rendering: Rendering,
+    started: Instant,
+    cur_pixel: AtomicU32,
+    /// Milliseconds after `started` of the last update
+    last_update: AtomicU64,
 }
 
 impl<'a> Progress<'a> {
-    pub fn new(state: &'a AppData, id: &'a Uuid) -> Self {
-        Self { state, id }
-    }
+    pub fn start(state: &'a AppData, id: &'a Uuid, pixels: u32) -> Result<Self, anyhow::Error> {
+        let rendering = Rendering::new(pixels);
+        state
+            .jobs
+            .set_status(id, ImageStatus::Rendering(rendering.clone()))?;
 
-    pub fn start(&self, pixels: u32) -> Result<(), anyhow::Error> {
-        let mut img_gen = self.state.img_gen.lock().map_err(map_poison_error)?;
-        let state = img_gen
-            .get_mut(self.id)
-            .ok_or(anyhow!("Image Gen not Started"))?;
-
-        *state = ImageStatus::Rendering(Rendering::new(pixels));
-
-        Ok(())
+        Ok(Self {
+            state,
+            id,
+            rendering,
+            started: Instant::now(),
+            cur_pixel: AtomicU32::new(0),
+            last_update: AtomicU64::new(0),
+        })
     }
 
-    pub fn inc(&self) -> Result<(), anyhow::Error> {
-        let mut img_gen = self.state.img_gen.lock().map_err(map_poison_error)?;
-        let state = img_gen
-            .get_mut(self.id)
-            .ok_or(anyhow!("Image Gen not Started"))?;
+    pub fn inc(&self, pixels: u32) -> Result<(), anyhow::Error> {
+        let cur_pixel = self.cur_pixel.fetch_add(pixels, Ordering::Relaxed) + pixels;
+        let now = self.started.elapsed().as_millis() as u64;
+        let last = self.last_update.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < PROGRESS_INTERVAL.as_millis() as u64
+            && cur_pixel < self.rendering.max_pixels
+        {
+            return Ok(());
+        }
+        // another thread is already updating
+        if self
+            .last_update
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Ok(());
+        }
 
-        match state {
-            ImageStatus::Rendering(r) => {
-                r.cur_pixel += 1;
-                r.elapsed = (Utc::now() - r.start).to_std()?;
-                let diff = r.max_pixels - r.cur_pixel;
-                let pixels_per_second = r.elapsed.as_secs() as f64 / r.cur_pixel as f64;
+        let mut r = self.rendering.clone();
+        r.cur_pixel = cur_pixel;
+        r.elapsed = (Utc::now() - r.start).to_std()?;
+        let diff = r.max_pixels - r.cur_pixel;
+        let pixels_per_second = r.elapsed.as_secs() as f64 / r.cur_pixel as f64;
 
-                let eta_secs = diff as f64 * pixels_per_second;
-                r.eta = Duration::from_secs(eta_secs as u64);
+        let eta_secs = diff as f64 * pixels_per_second;
+        r.eta = Duration::from_secs(eta_secs as u64);
 
-                r.percent = format!("{:.5}%", (r.cur_pixel as f64 / r.max_pixels as f64) * 100.);
-            }
-            _ => (),
-        }
+        r.percent = format!("{:.5}%", (r.cur_pixel as f64 / r.max_pixels as f64) * 100.);
 
-        Ok(())
+        self.state
+            .jobs
+            .set_status(self.id, ImageStatus::Rendering(r))
     }
 
-    pub fn complete(&self, img: RgbImage) -> Result<(), anyhow::Error> {
-        let mut img_gen = self.state.img_gen.lock().map_err(map_poison_error)?;
-
-        let state = img_gen
-            .get_mut(self.id)
-            .ok_or(anyhow!("Image Gen not Started"))?;
+    pub fn cancelled(&self) -> Result<(), anyhow::Error> {
+        self.state.jobs.set_status(self.id, ImageStatus::Cancelled)
+    }
 
-        *state = ImageStatus::Completed(Ok(img));
-        Ok(())
+    pub fn complete(&self, img: RenderedImage) -> Result<(), anyhow::Error> {
+        self.state
+            .jobs
+            .set_status(self.id, ImageStatus::Completed(Ok(Arc::new(img))))
     }
 }
@@ -1,26 +1,120 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
-use image::RgbImage;
-use uuid::Uuid;
+use chrono::Utc;
+use image::{
+    imageops::{self, FilterType},
+    ImageBuffer, Rgb, Rgb32FImage, RgbImage,
+};
+use raytracing_iow::color::Color;
+use tracing::info;
 
-use crate::{config::AppConfig, endpoints::status::ImageStatus};
+use crate::{
+    access::Access,
+    config::AppConfig,
+    endpoints::status::RenderStatistics,
+    events::{Events, NotifyingStore},
+    models::Output,
+    queue::RenderQueue,
+    render::to_image,
+    store::{FileStore, JobStore, MemoryStore},
+};
 
 pub type AppData = Arc<AppState>;
-pub type CompletedImageGen = Result<RgbImage, anyhow::Error>;
+pub type CompletedImageGen = Result<Arc<RenderedImage>, Arc<anyhow::Error>>;
+
+pub struct RenderedImage {
+    /// Framebuffer after post processing and before the output transform
+    pub linear: Rgb32FImage,
+    pub output: Output,
+    /// Multi-layer EXR of the requested render passes
+    pub aovs: Option<Vec<u8>>,
+    pub stats: Option<RenderStatistics>,
+}
+
+impl RenderedImage {
+    pub fn image(&self) -> RgbImage {
+        to_image(
+            self.linear.width(),
+            self.linear.height(),
+            &self.output,
+            self.colors(),
+            |c| (c * 255.) as u8,
+        )
+    }
+
+    pub fn image16(&self) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+        to_image(
+            self.linear.width(),
+            self.linear.height(),
+            &self.output,
+            self.colors(),
+            |c| (c * 65535.) as u16,
+        )
+    }
+
+    /// Copy scaled down to fit within `size`, without render passes
+    pub fn thumbnail(&self, size: u32) -> Self {
+        let (width, height) = (self.linear.width(), self.linear.height());
+        let scale = size as f64 / width.max(height) as f64;
+        let linear = if scale < 1. {
+            imageops::resize(
+                &self.linear,
+                ((width as f64 * scale).round() as u32).max(1),
+                ((height as f64 * scale).round() as u32).max(1),
+                FilterType::Triangle,
+            )
+        } else {
+            self.linear.clone()
+        };
+        Self {
+            linear,
+            output: self.output.clone(),
+            aovs: None,
+            stats: self.stats.clone(),
+        }
+    }
+
+    fn colors(&self) -> impl Iterator<Item = Color> + '_ {
+        self.linear
+            .pixels()
+            .map(|Rgb([r, g, b])| Color::new(*r as f64, *g as f64, *b as f64))
+    }
+}
 
 pub struct AppState {
-    pub img_gen: Mutex<HashMap<Uuid, ImageStatus<CompletedImageGen>>>,
+    pub jobs: Box<dyn JobStore>,
+    pub access: Access,
+    pub queue: RenderQueue,
+    pub events: Events,
     pub config: AppConfig,
 }
 
 impl AppState {
-    pub fn new(config: AppConfig) -> Arc<Self> {
-        Arc::new(Self {
-            img_gen: Default::default(),
+    pub fn new(config: AppConfig) -> Result<Arc<Self>, anyhow::Error> {
+        let store: Box<dyn JobStore> = match &config.job_store_dir {
+            Some(dir) => Box::new(FileStore::open(dir)?),
+            None => Box::<MemoryStore>::default(),
+        };
+        let events = Events::new();
+        let jobs = Box::new(NotifyingStore::new(store, events.clone()));
+        let queue = RenderQueue::new(config.render_workers, config.max_queued, events.clone());
+        let access = Access::new(&config)?;
+        Ok(Arc::new(Self {
+            jobs,
+            access,
+            queue,
+            events,
             config,
-        })
+        }))
+    }
+
+    /// Removes finished jobs older than the retention period
+    pub fn purge_expired(&self) -> Result<(), anyhow::Error> {
+        let retention = chrono::Duration::hours(self.config.job_retention_hours as i64);
+        let purged = self.jobs.purge(Utc::now() - retention)?;
+        if purged > 0 {
+            info!(message = "Purged expired jobs", count = purged);
+        }
+        Ok(())
     }
 }
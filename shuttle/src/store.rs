@@ -0,0 +1,349 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    endpoints::status::{ImageStatus, QueuePosition, RenderStatistics},
+    models::Output,
+    state::{CompletedImageGen, RenderedImage},
+    utils::map_poison_error,
+};
+
+/// Image generation job with the request it was started from
+#[derive(Clone)]
+pub struct Job {
+    /// Request body as it was submitted
+    pub request: serde_json::Value,
+    pub status: ImageStatus<CompletedImageGen>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Name of the API key that submitted the job
+    pub owner: Option<String>,
+}
+
+impl Job {
+    pub fn new(request: serde_json::Value) -> Self {
+        let now = Utc::now();
+        Self {
+            request,
+            status: ImageStatus::Queued(QueuePosition::default()),
+            created: now,
+            updated: now,
+            owner: None,
+        }
+    }
+
+    pub fn with_owner(mut self, owner: Option<&str>) -> Self {
+        self.owner = owner.map(str::to_string);
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            ImageStatus::Completed(_) | ImageStatus::Cancelled
+        )
+    }
+}
+
+/// Storage of image generation jobs
+pub trait JobStore: Send + Sync {
+    fn insert(&self, id: Uuid, job: Job) -> Result<(), anyhow::Error>;
+
+    fn get(&self, id: &Uuid) -> Result<Option<Job>, anyhow::Error>;
+
+    /// Replaces the status of an existing job
+    fn set_status(
+        &self,
+        id: &Uuid,
+        status: ImageStatus<CompletedImageGen>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Jobs that are queued or rendering
+    fn unfinished(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error>;
+
+    /// Every job, newest first
+    fn list(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error>;
+
+    /// Removes finished jobs last updated before `before`, returns how many were removed
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize, anyhow::Error>;
+}
+
+/// Keeps the jobs in memory, they are lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    jobs: Mutex<HashMap<Uuid, Job>>,
+}
+
+impl JobStore for MemoryStore {
+    fn insert(&self, id: Uuid, job: Job) -> Result<(), anyhow::Error> {
+        self.jobs.lock().map_err(map_poison_error)?.insert(id, job);
+        Ok(())
+    }
+
+    fn get(&self, id: &Uuid) -> Result<Option<Job>, anyhow::Error> {
+        Ok(self.jobs.lock().map_err(map_poison_error)?.get(id).cloned())
+    }
+
+    fn set_status(
+        &self,
+        id: &Uuid,
+        status: ImageStatus<CompletedImageGen>,
+    ) -> Result<(), anyhow::Error> {
+        let mut jobs = self.jobs.lock().map_err(map_poison_error)?;
+        let job = jobs.get_mut(id).ok_or(anyhow!("Image Gen not Started"))?;
+        job.status = status;
+        job.updated = Utc::now();
+        Ok(())
+    }
+
+    fn unfinished(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        Ok(self
+            .jobs
+            .lock()
+            .map_err(map_poison_error)?
+            .iter()
+            .filter(|(_, job)| !job.is_finished())
+            .map(|(id, job)| (*id, job.clone()))
+            .collect())
+    }
+
+    fn list(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        let mut jobs: Vec<(Uuid, Job)> = self
+            .jobs
+            .lock()
+            .map_err(map_poison_error)?
+            .iter()
+            .map(|(id, job)| (*id, job.clone()))
+            .collect();
+        jobs.sort_by_key(|(_, job)| std::cmp::Reverse(job.created));
+        Ok(jobs)
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize, anyhow::Error> {
+        let mut jobs = self.jobs.lock().map_err(map_poison_error)?;
+        let count = jobs.len();
+        jobs.retain(|_, job| !job.is_finished() || job.updated >= before);
+        Ok(count - jobs.len())
+    }
+}
+
+/// Writes every job to a directory so they survive restarts, reads are served from memory.
+/// Each job is a `{id}.json` record with its linear image in `{id}.linear.exr` and render passes
+/// in `{id}.exr`, records from before the linear image was kept have a `{id}.png` instead.
+pub struct FileStore {
+    dir: PathBuf,
+    memory: MemoryStore,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    request: serde_json::Value,
+    state: RecordState,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    #[serde(default)]
+    owner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum RecordState {
+    Queued,
+    Rendering,
+    Completed {
+        aovs: bool,
+        #[serde(default)]
+        output: Output,
+        #[serde(default)]
+        stats: Option<RenderStatistics>,
+    },
+    Failed(String),
+    Cancelled,
+}
+
+impl FileStore {
+    /// Loads the jobs stored in `dir`, jobs that were still rendering are queued again
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, anyhow::Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let memory = MemoryStore::default();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("json".as_ref()) {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| Uuid::parse_str(s).ok());
+            let Some(id) = id else { continue };
+            match Self::load(&dir, &id) {
+                Ok(job) => memory.insert(id, job)?,
+                Err(e) => warn!(message = "Skipping unreadable job", id = %id, err = ?e),
+            }
+        }
+        info!(message = "Opened job store", dir = ?dir);
+
+        Ok(Self { dir, memory })
+    }
+
+    fn path(dir: &Path, id: &Uuid, ext: &str) -> PathBuf {
+        dir.join(format!("{}.{}", id, ext))
+    }
+
+    fn load(dir: &Path, id: &Uuid) -> Result<Job, anyhow::Error> {
+        let record: Record = serde_json::from_slice(&fs::read(Self::path(dir, id, "json"))?)?;
+        let status = match record.state {
+            RecordState::Queued | RecordState::Rendering => {
+                ImageStatus::Queued(QueuePosition::default())
+            }
+            RecordState::Completed {
+                aovs,
+                output,
+                stats,
+            } => {
+                let linear = Self::path(dir, id, "linear.exr");
+                let linear = match linear.exists() {
+                    true => image::open(linear)?,
+                    false => image::open(Self::path(dir, id, "png"))?,
+                }
+                .to_rgb32f();
+                let aovs = aovs
+                    .then(|| fs::read(Self::path(dir, id, "exr")))
+                    .transpose()?;
+                ImageStatus::Completed(Ok(Arc::new(RenderedImage {
+                    linear,
+                    output,
+                    aovs,
+                    stats,
+                })))
+            }
+            RecordState::Failed(e) => ImageStatus::Completed(Err(Arc::new(anyhow!(e)))),
+            RecordState::Cancelled => ImageStatus::Cancelled,
+        };
+        Ok(Job {
+            request: record.request,
+            status,
+            created: record.created,
+            updated: record.updated,
+            owner: record.owner,
+        })
+    }
+
+    fn write(&self, id: &Uuid, job: &Job) -> Result<(), anyhow::Error> {
+        let state = match &job.status {
+            ImageStatus::Queued(_) => RecordState::Queued,
+            ImageStatus::Rendering(_) => RecordState::Rendering,
+            ImageStatus::Completed(Ok(c)) => RecordState::Completed {
+                aovs: c.aovs.is_some(),
+                output: c.output.clone(),
+                stats: c.stats.clone(),
+            },
+            ImageStatus::Completed(Err(e)) => RecordState::Failed(e.to_string()),
+            ImageStatus::Cancelled => RecordState::Cancelled,
+        };
+        let record = Record {
+            request: job.request.clone(),
+            state,
+            created: job.created,
+            updated: job.updated,
+            owner: job.owner.clone(),
+        };
+        self.write_file(id, "json", |path| {
+            Ok(fs::write(path, serde_json::to_vec(&record)?)?)
+        })
+    }
+
+    /// Writes then renames so a crash never leaves a half written file
+    fn write_file(
+        &self,
+        id: &Uuid,
+        ext: &str,
+        write: impl FnOnce(&Path) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let tmp = Self::path(&self.dir, id, &format!("{}.tmp", ext));
+        write(&tmp)?;
+        fs::rename(tmp, Self::path(&self.dir, id, ext))?;
+        Ok(())
+    }
+
+    fn remove_files(&self, id: &Uuid) {
+        for ext in ["json", "linear.exr", "png", "exr"] {
+            let _ = fs::remove_file(Self::path(&self.dir, id, ext));
+        }
+    }
+}
+
+impl JobStore for FileStore {
+    fn insert(&self, id: Uuid, job: Job) -> Result<(), anyhow::Error> {
+        self.write(&id, &job)?;
+        self.memory.insert(id, job)
+    }
+
+    fn get(&self, id: &Uuid) -> Result<Option<Job>, anyhow::Error> {
+        self.memory.get(id)
+    }
+
+    fn set_status(
+        &self,
+        id: &Uuid,
+        status: ImageStatus<CompletedImageGen>,
+    ) -> Result<(), anyhow::Error> {
+        if let ImageStatus::Completed(Ok(c)) = &status {
+            self.write_file(id, "linear.exr", |path| {
+                Ok(c.linear.save_with_format(path, ImageFormat::OpenExr)?)
+            })?;
+            if let Some(aovs) = &c.aovs {
+                self.write_file(id, "exr", |path| Ok(fs::write(path, aovs)?))?;
+            }
+        }
+        // progress of a render only lives in memory, the record keeps the state changes
+        let progress = matches!(status, ImageStatus::Rendering(_))
+            && matches!(
+                self.memory.get(id)?.map(|job| job.status),
+                Some(ImageStatus::Rendering(_))
+            );
+        self.memory.set_status(id, status)?;
+        if progress {
+            return Ok(());
+        }
+        let job = self
+            .memory
+            .get(id)?
+            .ok_or(anyhow!("Image Gen not Started"))?;
+        self.write(id, &job)
+    }
+
+    fn unfinished(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        self.memory.unfinished()
+    }
+
+    fn list(&self) -> Result<Vec<(Uuid, Job)>, anyhow::Error> {
+        self.memory.list()
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize, anyhow::Error> {
+        let mut jobs = self.memory.jobs.lock().map_err(map_poison_error)?;
+        let expired: Vec<Uuid> = jobs
+            .iter()
+            .filter(|(_, job)| job.is_finished() && job.updated < before)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            jobs.remove(id);
+            self.remove_files(id);
+        }
+        Ok(expired.len())
+    }
+}
@@ -1,32 +1,13 @@
 use anyhow::anyhow;
-use axum::{
-    http::{header, HeaderMap, StatusCode},
-    response::IntoResponse,
-};
-use image::{ImageFormat, RgbImage};
-use std::{
-    io::{BufWriter, Cursor},
-    sync::PoisonError,
-};
+use axum::http::StatusCode;
+use serde_json::{Map, Value};
+use std::sync::PoisonError;
 
 #[inline]
 pub fn map_poison_error<I>(e: PoisonError<I>) -> anyhow::Error {
     anyhow!("Poison Error: {}", e)
 }
 
-pub fn image_into_response(img: RgbImage) -> Result<axum::response::Response, anyhow::Error> {
-    let mut headers = HeaderMap::new();
-    headers.insert(header::CONTENT_TYPE, "image/png".parse()?);
-
-    let mut buffer = BufWriter::new(Cursor::new(Vec::new()));
-    img.write_to(&mut buffer, ImageFormat::Png)?;
-
-    let img = buffer.into_inner()?.into_inner();
-    headers.insert(header::CONTENT_LENGTH, img.len().into());
-
-    Ok((StatusCode::OK, headers, img).into_response())
-}
-
 #[inline]
 pub fn someting_went_wrong() -> (StatusCode, String) {
     (
@@ -42,3 +23,24 @@ pub fn anyhow_error_http_response(e: &anyhow::Error) -> (StatusCode, String) {
         format!("Error occured during processing: {}", e),
     )
 }
+
+/// Applies a JSON merge patch, objects are merged key by key, `null` removes a key and
+/// anything else including arrays replaces the target
+pub fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(&key);
+            } else {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}